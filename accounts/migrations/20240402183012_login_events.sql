@@ -0,0 +1,18 @@
+-- Purpose: Keep a persistent history of every session creation attempt,
+-- independent from the sessions table (which loses rows on logout).
+CREATE TABLE "login_events" (
+    "id" TEXT NOT NULL PRIMARY KEY,
+    "account_id" TEXT REFERENCES "accounts" ("id") ON DELETE CASCADE,
+    "email" TEXT NOT NULL,
+    "session_id" TEXT,
+    "ip_address" TEXT NOT NULL,
+    "user_agent" TEXT,
+    "device_name" TEXT NOT NULL,
+    "device_description" TEXT NOT NULL,
+    "device_type" TEXT NOT NULL,
+    "outcome" TEXT NOT NULL,
+    "reason" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "login_events_account_id_created_at" ON "login_events" ("account_id", "created_at" DESC);
@@ -0,0 +1,3 @@
+-- Purpose: Let each account opt out of new device sign-in notification emails.
+ALTER TABLE "accounts"
+ADD COLUMN "new_device_sign_in_email" BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+-- Purpose: Store the raw user agent the session was created with.
+ALTER TABLE "sessions"
+ADD COLUMN "user_agent" TEXT;
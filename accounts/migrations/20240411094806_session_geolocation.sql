@@ -0,0 +1,7 @@
+-- Purpose: Bring back the session location, now resolved from a local
+-- geolocation database. Both columns stay NULL when it can't be resolved.
+ALTER TABLE "sessions"
+ADD COLUMN "country_code" TEXT;
+
+ALTER TABLE "sessions"
+ADD COLUMN "city" TEXT;
@@ -0,0 +1,39 @@
+-- Purpose: Replace the all-or-nothing administrator group with roles
+-- that grant fine-grained permissions. The accounts "group" column now
+-- holds the name of the role the account belongs to.
+CREATE TABLE "roles" (
+    "name" TEXT NOT NULL PRIMARY KEY,
+    "description" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE "role_permissions" (
+    "role" TEXT NOT NULL REFERENCES "roles" ("name") ON DELETE CASCADE ON UPDATE CASCADE,
+    "permission" TEXT NOT NULL,
+    PRIMARY KEY ("role", "permission")
+);
+
+-- Keep the two groups that already exist as roles.
+INSERT INTO "roles" ("name", "description", "created_at")
+VALUES
+    ('administrator', 'Full access to every administrative action', NOW()),
+    ('moderator', 'Can read and edit profiles but not credentials or roles', NOW()),
+    ('default', 'Regular account without administrative access', NOW());
+
+INSERT INTO "role_permissions" ("role", "permission")
+VALUES
+    ('administrator', 'accounts.read'),
+    ('administrator', 'accounts.edit'),
+    ('administrator', 'accounts.change_email'),
+    ('administrator', 'accounts.change_password'),
+    ('administrator', 'accounts.delete'),
+    ('administrator', 'sessions.read'),
+    ('administrator', 'sessions.revoke'),
+    ('administrator', 'groups.assign'),
+    ('moderator', 'accounts.read'),
+    ('moderator', 'accounts.edit'),
+    ('moderator', 'sessions.read');
+
+-- Every account must belong to an existing role.
+ALTER TABLE "accounts"
+ADD CONSTRAINT "group_role" FOREIGN KEY ("group") REFERENCES "roles" ("name") ON UPDATE CASCADE;
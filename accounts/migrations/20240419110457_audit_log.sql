@@ -0,0 +1,34 @@
+-- Purpose: Append-only, hash-chained log of administrative and
+-- security-sensitive actions. Rows are never updated or deleted and
+-- are kept even after the accounts involved are gone.
+CREATE TABLE "audit_log" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "actor_id" TEXT NOT NULL,
+    "target_id" TEXT NOT NULL,
+    "action" TEXT NOT NULL,
+    "diff" JSONB NOT NULL,
+    "ip_address" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL,
+    "previous_hash" TEXT NOT NULL,
+    "hash" TEXT NOT NULL
+);
+
+CREATE INDEX "audit_log_actor_id" ON "audit_log" ("actor_id");
+CREATE INDEX "audit_log_target_id" ON "audit_log" ("target_id");
+
+CREATE FUNCTION "audit_log_is_append_only"() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "audit_log_no_update_or_delete"
+BEFORE UPDATE OR DELETE ON "audit_log"
+FOR EACH ROW EXECUTE FUNCTION "audit_log_is_append_only"();
+
+CREATE TRIGGER "audit_log_no_truncate"
+BEFORE TRUNCATE ON "audit_log"
+FOR EACH STATEMENT EXECUTE FUNCTION "audit_log_is_append_only"();
+
+INSERT INTO "role_permissions" ("role", "permission")
+VALUES ('administrator', 'audit_log.read');
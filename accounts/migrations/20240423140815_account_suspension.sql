@@ -0,0 +1,17 @@
+-- Purpose: Let admins suspend accounts, either for good or until a given date.
+-- An account is suspended while "suspended_at" is set and "suspended_until"
+-- is either NULL or still in the future, so suspensions lift themselves.
+ALTER TABLE "accounts"
+ADD COLUMN "suspended_at" TIMESTAMP;
+
+ALTER TABLE "accounts"
+ADD COLUMN "suspended_until" TIMESTAMP;
+
+ALTER TABLE "accounts"
+ADD COLUMN "suspended_by" TEXT REFERENCES "accounts" ("id") ON DELETE SET NULL;
+
+ALTER TABLE "accounts"
+ADD COLUMN "suspension_reason" TEXT;
+
+INSERT INTO "role_permissions" ("role", "permission")
+VALUES ('administrator', 'accounts.suspend');
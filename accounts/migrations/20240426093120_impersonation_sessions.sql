@@ -0,0 +1,7 @@
+-- Purpose: Mark sessions created by an admin to act as another account,
+-- so they show up as such in the session list of the impersonated account.
+ALTER TABLE "sessions"
+ADD COLUMN "impersonator_id" TEXT REFERENCES "accounts" ("id") ON DELETE CASCADE;
+
+INSERT INTO "role_permissions" ("role", "permission")
+VALUES ('administrator', 'accounts.impersonate');
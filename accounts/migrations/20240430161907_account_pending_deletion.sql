@@ -0,0 +1,7 @@
+-- Purpose: Keep deleted accounts around for a grace period, during which
+-- the owner can still sign in and cancel. Accounts are purged once
+-- "deletion_scheduled_for" is in the past.
+ALTER TABLE "accounts"
+ADD COLUMN "deletion_scheduled_for" TIMESTAMP;
+
+CREATE INDEX "accounts_deletion_scheduled_for" ON "accounts" ("deletion_scheduled_for") WHERE "deletion_scheduled_for" IS NOT NULL;
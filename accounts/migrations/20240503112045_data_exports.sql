@@ -0,0 +1,12 @@
+-- Purpose: Hold the archives of "download my data" requests. They are built
+-- in the background and dropped by the purge task once "expires_at" is past.
+CREATE TABLE "data_exports" (
+    "id" TEXT NOT NULL PRIMARY KEY,
+    "account_id" TEXT NOT NULL REFERENCES "accounts" ("id") ON DELETE CASCADE,
+    "status" TEXT NOT NULL,
+    "archive" BYTEA,
+    "created_at" TIMESTAMP NOT NULL,
+    "expires_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "data_exports_account_id" ON "data_exports" ("account_id");
@@ -0,0 +1,5 @@
+-- Purpose: Let administrators bulk import and export accounts.
+INSERT INTO "role_permissions" ("role", "permission")
+VALUES
+    ('administrator', 'accounts.import'),
+    ('administrator', 'accounts.export');
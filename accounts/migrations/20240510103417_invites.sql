@@ -0,0 +1,30 @@
+-- Purpose: Invite codes for invite-only registration. Every redemption is
+-- kept so it's known which account joined through which invite.
+CREATE TABLE "invites" (
+    "code" TEXT NOT NULL PRIMARY KEY,
+    "created_by" TEXT REFERENCES "accounts" ("id") ON DELETE SET NULL,
+    "max_uses" INTEGER,
+    "uses" INTEGER NOT NULL DEFAULT 0,
+    "expires_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "invites_created_by" ON "invites" ("created_by");
+
+CREATE TABLE "invite_redemptions" (
+    "account_id" TEXT NOT NULL PRIMARY KEY REFERENCES "accounts" ("id") ON DELETE CASCADE,
+    "invite_code" TEXT NOT NULL REFERENCES "invites" ("code") ON DELETE CASCADE,
+    "redeemed_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "invite_redemptions_invite_code" ON "invite_redemptions" ("invite_code");
+
+-- The invite is checked when the verification code is sent and redeemed
+-- once the account is created.
+ALTER TABLE "account_creation_verifications"
+ADD COLUMN "invite_code" TEXT;
+
+-- Administrators can create invites without a quota.
+INSERT INTO "role_permissions" ("role", "permission")
+VALUES
+    ('administrator', 'invites.create');
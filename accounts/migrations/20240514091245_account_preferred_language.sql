@@ -0,0 +1,4 @@
+-- Purpose: Let accounts pick the language of the emails they receive.
+-- NULL means the default locale.
+ALTER TABLE "accounts"
+ADD COLUMN "preferred_language" TEXT;
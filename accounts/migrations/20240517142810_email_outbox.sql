@@ -0,0 +1,21 @@
+-- Purpose: Queue outgoing emails in the same transaction as the change that
+-- triggers them. A background worker delivers them, retrying failures with an
+-- exponential backoff until "max attempts" is reached and the row goes "dead".
+CREATE TABLE "email_outbox" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "recipient" TEXT NOT NULL,
+    "subject" TEXT NOT NULL,
+    "body" TEXT NOT NULL,
+    "html" BOOLEAN NOT NULL,
+    "status" TEXT NOT NULL DEFAULT 'pending',
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "last_error" TEXT,
+    "next_attempt_at" TIMESTAMP NOT NULL,
+    "created_at" TIMESTAMP NOT NULL,
+    "sent_at" TIMESTAMP
+);
+
+CREATE INDEX "email_outbox_status_next_attempt_at" ON "email_outbox" ("status", "next_attempt_at");
+
+INSERT INTO "role_permissions" ("role", "permission")
+VALUES ('administrator', 'email_outbox.manage');
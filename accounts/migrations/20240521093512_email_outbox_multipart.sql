@@ -0,0 +1,12 @@
+-- Purpose: Keep both the plain text and the HTML version of queued emails so
+-- they can be sent as multipart/alternative. The text of HTML emails already in
+-- the queue is approximated by stripping their tags.
+ALTER TABLE "email_outbox" RENAME COLUMN "body" TO "text_body";
+ALTER TABLE "email_outbox" ADD COLUMN "html_body" TEXT;
+
+UPDATE "email_outbox"
+SET "html_body" = "text_body",
+    "text_body" = regexp_replace("text_body", '<[^>]*>', '', 'g')
+WHERE "html";
+
+ALTER TABLE "email_outbox" DROP COLUMN "html";
@@ -0,0 +1,13 @@
+-- Purpose: Let the owner of the previous email address undo an email change.
+-- A token is stored on every change and mailed to the old address, using it
+-- restores that address and revokes every session of the account.
+CREATE TABLE "email_change_reverts" (
+    "token" TEXT NOT NULL PRIMARY KEY,
+    "account_id" TEXT NOT NULL REFERENCES "accounts" ("id") ON DELETE CASCADE,
+    "old_email" TEXT NOT NULL,
+    "new_email" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL,
+    "expires_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "email_change_reverts_account_id" ON "email_change_reverts" ("account_id");
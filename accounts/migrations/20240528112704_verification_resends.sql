@@ -0,0 +1,13 @@
+-- Purpose: Let pending verification codes be resent without starting the flow
+-- over. Every resend is remembered per flow and target so they can be rate
+-- limited, and the address an email change is waiting on is now kept so its
+-- codes can be resent to it.
+CREATE TABLE "verification_resends" (
+    "flow" TEXT NOT NULL,
+    "target" TEXT NOT NULL,
+    "sent_at" TIMESTAMP NOT NULL,
+    PRIMARY KEY ("flow", "target")
+);
+
+ALTER TABLE "accounts"
+ADD COLUMN "new_email" TEXT;
@@ -0,0 +1,11 @@
+-- Purpose: Keep a single pending signup per email and per handle. Older
+-- duplicates are dropped in favour of the newest row, and the unique indexes
+-- stop two signups from holding the same email or handle at the same time.
+DELETE FROM "account_creation_verifications" AS "older"
+USING "account_creation_verifications" AS "newer"
+WHERE ("older"."email" = "newer"."email" OR "older"."handle" = "newer"."handle")
+    AND ("older"."verification_code_created_at", "older"."ctid")
+        < ("newer"."verification_code_created_at", "newer"."ctid");
+
+CREATE UNIQUE INDEX "account_creation_verifications_email" ON "account_creation_verifications" ("email");
+CREATE UNIQUE INDEX "account_creation_verifications_handle" ON "account_creation_verifications" ("handle");
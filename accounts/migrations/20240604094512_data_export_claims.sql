@@ -0,0 +1,15 @@
+-- Purpose: Let the background worker claim pending data exports, so exports
+-- left behind by a restart are noticed, and allow a single pending export per
+-- account. Older duplicates are marked as failed in favour of the newest one.
+ALTER TABLE "data_exports" ADD COLUMN "claimed_at" TIMESTAMP;
+
+UPDATE "data_exports" AS "older"
+SET "status" = 'failed'
+FROM "data_exports" AS "newer"
+WHERE "older"."account_id" = "newer"."account_id"
+    AND "older"."status" = 'pending'
+    AND "newer"."status" = 'pending'
+    AND ("older"."created_at", "older"."id") < ("newer"."created_at", "newer"."id");
+
+CREATE UNIQUE INDEX "data_exports_account_id_pending" ON "data_exports" ("account_id")
+WHERE "status" = 'pending';
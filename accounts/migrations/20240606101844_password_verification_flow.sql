@@ -0,0 +1,9 @@
+-- Purpose: Forgot password and password change share the same code columns
+-- but not the same code format and time to live, so remember which flow
+-- issued the code. Pending codes can't be told apart and are dropped.
+ALTER TABLE "accounts" ADD COLUMN "new_password_verification_flow" TEXT;
+
+UPDATE "accounts"
+SET "new_password_verification_code" = NULL,
+    "new_password_verification_code_created_at" = NULL
+WHERE "new_password_verification_code" IS NOT NULL;
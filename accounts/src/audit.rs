@@ -0,0 +1,138 @@
+use crate::{
+    error::{DatabaseError, Error},
+    models::AuditAction,
+    prelude::*,
+};
+use chrono::{NaiveDateTime, SubsecRound, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+
+pub const AUDIT_LOG_GENESIS_HASH: &str = "";
+
+pub struct NewAuditLogEntry {
+    pub actor_id: String,
+    pub target_id: String,
+    pub action: AuditAction,
+    pub diff: Value,
+    pub ip_address: String,
+}
+
+pub fn hash_audit_log_entry(
+    previous_hash: &str,
+    actor_id: &str,
+    target_id: &str,
+    action: &str,
+    diff: &Value,
+    ip_address: &str,
+    created_at: &NaiveDateTime,
+) -> String {
+    // serde_json KEEPS OBJECT KEYS SORTED, SO THE DIFF SERIALIZES
+    // THE SAME WAY AFTER A ROUND TRIP THROUGH A JSONB COLUMN
+
+    let diff = diff.to_string();
+    let created_at = created_at.format("%Y-%m-%dT%H:%M:%S%.6f").to_string();
+
+    let mut hasher = Sha256::new();
+
+    // PREFIX EVERY FIELD WITH ITS LENGTH SO THEIR BOUNDARIES CAN'T BE SHIFTED
+
+    for field in [
+        previous_hash,
+        actor_id,
+        target_id,
+        action,
+        diff.as_str(),
+        ip_address,
+        created_at.as_str(),
+    ] {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field.as_bytes());
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| f!("{:02x}", byte))
+        .collect()
+}
+
+pub async fn record_audit_log_entry(
+    connection: &mut PgConnection,
+    entry: NewAuditLogEntry,
+) -> Result<()> {
+    // SERIALIZE WRITERS UNTIL THE END OF THE TRANSACTION
+    // SO TWO ENTRIES NEVER CHAIN FROM THE SAME PREVIOUS HASH
+
+    sqlx::query!("LOCK TABLE audit_log IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *connection)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    // GET THE HASH OF THE LAST ENTRY
+
+    let query = sqlx::query!(
+        r#"
+            SELECT hash
+            FROM audit_log
+            ORDER BY id DESC
+            LIMIT 1
+        "#
+    );
+
+    let previous_hash = query
+        .fetch_optional(&mut *connection)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .map(|result| result.hash)
+        .unwrap_or_else(|| AUDIT_LOG_GENESIS_HASH.to_string());
+
+    // POSTGRES ONLY KEEPS MICROSECONDS, TRUNCATE BEFORE HASHING
+    // SO THE STORED TIMESTAMP STILL MATCHES THE HASH
+
+    let created_at = Utc::now().naive_utc().trunc_subsecs(6);
+    let action = entry.action.to_string();
+
+    let hash = hash_audit_log_entry(
+        &previous_hash,
+        &entry.actor_id,
+        &entry.target_id,
+        &action,
+        &entry.diff,
+        &entry.ip_address,
+        &created_at,
+    );
+
+    // INSERT THE NEW ENTRY
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO audit_log (
+                "actor_id",
+                "target_id",
+                "action",
+                "diff",
+                "ip_address",
+                "created_at",
+                "previous_hash",
+                "hash"
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        entry.actor_id,
+        entry.target_id,
+        action,
+        entry.diff,
+        entry.ip_address,
+        created_at,
+        previous_hash,
+        hash
+    );
+
+    query
+        .execute(&mut *connection)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
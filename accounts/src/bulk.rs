@@ -0,0 +1,495 @@
+use crate::{
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    config::CONFIG,
+    database::DATABASE_POOL,
+    encryption,
+    error::{DatabaseError, Error, ImportError},
+    models::{
+        AccountFileFormat, AuditAction, ExportedAccount, Gender, ImportReport, ImportRowError,
+        ImportedAccount,
+    },
+    prelude::*,
+    random::get_random_string,
+    sanitize_handle,
+};
+use chrono::Utc;
+use serde_json::json;
+use std::{collections::HashSet, str::FromStr};
+use validator::Validate;
+
+const BCRYPT_HASH_PREFIXES: [&str; 4] = ["$2a$", "$2b$", "$2x$", "$2y$"];
+const BCRYPT_HASH_LENGTH: usize = 60;
+
+fn is_bcrypt_hash(password: &str) -> bool {
+    password.len() == BCRYPT_HASH_LENGTH
+        && BCRYPT_HASH_PREFIXES
+            .iter()
+            .any(|prefix| password.starts_with(prefix))
+}
+
+type AccountRecord = (usize, std::result::Result<ImportedAccount, String>);
+
+fn parse_accounts(format: AccountFileFormat, data: &str) -> Vec<AccountRecord> {
+    match format {
+        // ROWS ARE COUNTED FROM THE FIRST RECORD AFTER THE HEADER
+        AccountFileFormat::Csv => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes())
+            .deserialize::<ImportedAccount>()
+            .enumerate()
+            .map(|(index, record)| (index + 1, record.map_err(|err| err.to_string())))
+            .collect(),
+
+        // ROWS ARE LINE NUMBERS, BLANK LINES ARE SKIPPED
+        AccountFileFormat::Jsonl => data
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                (
+                    index + 1,
+                    serde_json::from_str::<ImportedAccount>(line).map_err(|err| err.to_string()),
+                )
+            })
+            .collect(),
+    }
+}
+
+// Audit log target of changes that cover every account
+const ALL_ACCOUNTS_TARGET_ID: &str = "*";
+
+async fn validate_accounts(
+    records: Vec<AccountRecord>,
+    assigning_account_id: Option<&str>,
+) -> Result<(Vec<ImportedAccount>, Vec<ImportRowError>, usize)> {
+    let total = records.len();
+
+    let mut accounts: Vec<(usize, ImportedAccount)> = Vec::new();
+    let mut errors: Vec<ImportRowError> = Vec::new();
+
+    let mut seen_handles = HashSet::new();
+    let mut seen_emails = HashSet::new();
+
+    // GET THE ROLES AN IMPORTED ACCOUNT CAN BE PUT IN, AN IMPORT FROM THE API
+    // CAN ONLY USE ROLES THAT GRANT NO PERMISSION THE IMPORTING ACCOUNT LACKS
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                name,
+                ($1::TEXT IS NULL OR NOT EXISTS(
+                    SELECT permission
+                    FROM role_permissions
+                    WHERE role = roles.name
+                    EXCEPT
+                    SELECT role_permissions.permission
+                    FROM accounts
+                    JOIN role_permissions ON role_permissions.role = accounts."group"
+                    WHERE accounts.id = $1
+                )) AS "within_own_permissions!"
+            FROM roles
+        "#,
+        assigning_account_id
+    );
+
+    let roles = query
+        .fetch_all(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    let known_roles: HashSet<String> = roles.iter().map(|role| role.name.to_owned()).collect();
+    let assignable_roles: HashSet<String> = roles
+        .into_iter()
+        .filter(|role| role.within_own_permissions)
+        .map(|role| role.name)
+        .collect();
+
+    // APPLY THE SAME RULES AS ACCOUNT CREATION TO EVERY RECORD
+
+    for (row, record) in records {
+        let mut account = match record {
+            Ok(account) => account,
+            Err(message) => {
+                errors.push(ImportRowError { row, message });
+                continue;
+            }
+        };
+
+        if let Err(err) = account.validate() {
+            errors.push(ImportRowError {
+                row,
+                message: err.to_string(),
+            });
+            continue;
+        }
+
+        account.handle = match sanitize_handle(&account.handle) {
+            Ok(handle) if !handle.is_empty() => handle,
+            _ => {
+                errors.push(ImportRowError {
+                    row,
+                    message: f!("Invalid handle {}", account.handle),
+                });
+                continue;
+            }
+        };
+        account.name = account.name.trim().to_string();
+        account.email = account.email.trim().to_string();
+        account.country_code = account.country_code.to_lowercase().trim().to_string();
+        account.group = Some(
+            account
+                .group
+                .unwrap_or_else(|| CONFIG.default_group.to_owned()),
+        );
+
+        if let Some(group) = account
+            .group
+            .as_ref()
+            .filter(|group| !known_roles.contains(*group))
+        {
+            errors.push(ImportRowError {
+                row,
+                message: f!("Unknown group {}", group),
+            });
+            continue;
+        }
+
+        if let Some(group) = account
+            .group
+            .as_ref()
+            .filter(|group| !assignable_roles.contains(*group))
+        {
+            errors.push(ImportRowError {
+                row,
+                message: f!(
+                    "Group {} grants permissions the importer doesn't have",
+                    group
+                ),
+            });
+            continue;
+        }
+
+        if !seen_handles.insert(account.handle.to_owned()) {
+            errors.push(ImportRowError {
+                row,
+                message: f!("Handle {} appears more than once", account.handle),
+            });
+            continue;
+        }
+
+        if !seen_emails.insert(account.email.to_owned()) {
+            errors.push(ImportRowError {
+                row,
+                message: f!("Email {} appears more than once", account.email),
+            });
+            continue;
+        }
+
+        accounts.push((row, account));
+    }
+
+    // CHECK FOR HANDLES AND EMAILS ALREADY IN USE
+
+    let handles: Vec<String> = accounts
+        .iter()
+        .map(|(_, account)| account.handle.to_owned())
+        .collect();
+    let emails: Vec<String> = accounts
+        .iter()
+        .map(|(_, account)| account.email.to_owned())
+        .collect();
+
+    let query = sqlx::query!(
+        r#"
+            SELECT handle, email
+            FROM accounts
+            WHERE handle = ANY($1) OR email = ANY($2)
+        "#,
+        &handles,
+        &emails
+    );
+
+    let conflicts = query
+        .fetch_all(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    let taken_handles: HashSet<String> = conflicts
+        .iter()
+        .map(|conflict| conflict.handle.to_owned())
+        .collect();
+    let taken_emails: HashSet<String> = conflicts
+        .into_iter()
+        .map(|conflict| conflict.email)
+        .collect();
+
+    let mut valid_accounts = Vec::new();
+
+    for (row, account) in accounts {
+        if taken_handles.contains(&account.handle) {
+            errors.push(ImportRowError {
+                row,
+                message: f!("Handle {} is already in use", account.handle),
+            });
+        } else if taken_emails.contains(&account.email) {
+            errors.push(ImportRowError {
+                row,
+                message: f!("Email {} is already in use", account.email),
+            });
+        } else {
+            valid_accounts.push(account);
+        }
+    }
+
+    errors.sort_by_key(|error| error.row);
+
+    Ok((valid_accounts, errors, total))
+}
+
+// The assigning account limits the groups the accounts can be put in,
+// it's None for the command line which can use every group
+pub async fn import_accounts(
+    format: AccountFileFormat,
+    data: &str,
+    dry_run: bool,
+    assigning_account_id: Option<&str>,
+    actor_id: &str,
+    ip_address: &str,
+) -> Result<ImportReport> {
+    let records = parse_accounts(format, data);
+
+    import_account_records(records, dry_run, assigning_account_id, actor_id, ip_address).await
+}
+
+pub async fn import_account(
+    account: ImportedAccount,
+    actor_id: &str,
+    ip_address: &str,
+) -> Result<ImportReport> {
+    import_account_records(vec![(1, Ok(account))], false, None, actor_id, ip_address).await
+}
+
+async fn import_account_records(
+    records: Vec<AccountRecord>,
+    dry_run: bool,
+    assigning_account_id: Option<&str>,
+    actor_id: &str,
+    ip_address: &str,
+) -> Result<ImportReport> {
+    let (accounts, errors, total) = validate_accounts(records, assigning_account_id).await?;
+
+    let mut report = ImportReport {
+        dry_run,
+        total,
+        valid: accounts.len(),
+        imported: 0,
+        errors,
+    };
+
+    // NOTHING IS WRITTEN ON A DRY RUN OR IF ANY RECORD IS INVALID
+
+    if dry_run || !report.errors.is_empty() {
+        return Ok(report);
+    }
+
+    // HASH THE PLAINTEXT PASSWORDS, BCRYPT HASHES ARE KEPT AS THEY ARE
+
+    let accounts = async_std::task::spawn_blocking(move || {
+        accounts
+            .into_iter()
+            .map(|mut account| {
+                if !is_bcrypt_hash(&account.password) {
+                    account.password = encryption::encrypt_string(&account.password)?;
+                }
+
+                Ok(account)
+            })
+            .collect::<Result<Vec<ImportedAccount>>>()
+    })
+    .await?;
+
+    // INSERT EVERY ACCOUNT IN A SINGLE TRANSACTION, ALL OR NOTHING
+
+    let mut transaction = DATABASE_POOL
+        .begin()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    for account in accounts {
+        let id = get_random_string(CONFIG.account_id_length);
+
+        let query = sqlx::query!(
+            r#"
+                INSERT INTO accounts (
+                    "id",
+                    "handle",
+                    "name",
+                    "email",
+                    "password",
+                    "group",
+                    "gender",
+                    "email_is_public",
+                    "gender_is_public",
+                    "country_code",
+                    "created_at"
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+            id,
+            account.handle,
+            account.name,
+            account.email,
+            account.password,
+            account.group,
+            account.gender.unwrap_or(Gender::NotSpecified).to_string(),
+            account.email_is_public.unwrap_or(false),
+            account.gender_is_public.unwrap_or(false),
+            account.country_code,
+            account.created_at.unwrap_or_else(|| Utc::now().naive_utc())
+        );
+
+        query
+            .execute(&mut *transaction)
+            .await
+            .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+        record_audit_log_entry(
+            &mut transaction,
+            NewAuditLogEntry {
+                actor_id: actor_id.to_owned(),
+                target_id: id,
+                action: AuditAction::AccountImport,
+                diff: json!({
+                    "before": null,
+                    "after": {
+                        "handle": account.handle,
+                        "email": account.email,
+                        "group": account.group,
+                    },
+                }),
+                ip_address: ip_address.to_owned(),
+            },
+        )
+        .await?;
+
+        report.imported += 1;
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(report)
+}
+
+// Password hashes are only written when asked for, the export is recorded
+// in the audit log either way
+pub async fn export_accounts(
+    format: AccountFileFormat,
+    include_password_hashes: bool,
+    actor_id: &str,
+    ip_address: &str,
+) -> Result<String> {
+    let mut transaction = DATABASE_POOL
+        .begin()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                id,
+                handle,
+                name,
+                email,
+                password,
+                "group",
+                gender,
+                email_is_public,
+                gender_is_public,
+                country_code,
+                created_at
+            FROM accounts
+            ORDER BY created_at, id
+        "#
+    );
+
+    let accounts: Vec<ExportedAccount> = query
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .into_iter()
+        .map(|account| ExportedAccount {
+            id: account.id,
+            handle: account.handle,
+            name: account.name,
+            email: account.email,
+            password: include_password_hashes.then_some(account.password),
+            group: account.group,
+            gender: Gender::from_str(&account.gender).unwrap_or(Gender::NotSpecified),
+            email_is_public: account.email_is_public,
+            gender_is_public: account.gender_is_public,
+            country_code: account.country_code,
+            created_at: account.created_at,
+        })
+        .collect();
+
+    // RECORD WHO TOOK THE ACCOUNTS OUT OF THE SERVICE
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: actor_id.to_owned(),
+            target_id: ALL_ACCOUNTS_TARGET_ID.to_owned(),
+            action: AuditAction::AccountExport,
+            diff: json!({
+                "before": null,
+                "after": {
+                    "format": format.to_string(),
+                    "accounts": accounts.len(),
+                    "include_password_hashes": include_password_hashes,
+                },
+            }),
+            ip_address: ip_address.to_owned(),
+        },
+    )
+    .await?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    match format {
+        AccountFileFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+
+            for account in accounts {
+                writer
+                    .serialize(account)
+                    .map_err(|err| Error::Import(ImportError::Serialize(err.to_string())))?;
+            }
+
+            let bytes = writer
+                .into_inner()
+                .map_err(|err| Error::Import(ImportError::Serialize(err.to_string())))?;
+
+            String::from_utf8(bytes)
+                .map_err(|err| Error::Import(ImportError::Serialize(err.to_string())))
+        }
+        AccountFileFormat::Jsonl => {
+            let mut lines = String::new();
+
+            for account in accounts {
+                let line = serde_json::to_string(&account)
+                    .map_err(|err| Error::Import(ImportError::Serialize(err.to_string())))?;
+
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+
+            Ok(lines)
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use clap::{Parser, Subcommand};
+use serde_json::json;
+use sqlx::migrate;
+use std::path::PathBuf;
+
+use crate::{
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    bulk::{export_accounts, import_account, import_accounts},
+    config::CONFIG,
+    database::DATABASE_POOL,
+    encryption,
+    error::{CliError, DatabaseError, Error, ImportError},
+    models::{AccountFileFormat, AuditAction, Gender, ImportReport, ImportedAccount},
+    prelude::*,
+    random::get_random_string,
+    sanitize_handle,
+};
+
+// Actor and address recorded in the audit log for changes made from the command line
+const CLI_ACTOR_ID: &str = "cli";
+const CLI_IP_ADDRESS: &str = "local";
+
+const ADMINISTRATOR_GROUP: &str = "administrator";
+const GENERATED_PASSWORD_LENGTH: usize = 24;
+
+#[derive(Debug, Parser)]
+#[command(about = "Accounts service, runs the server when no command is given")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Import accounts from a CSV or JSONL file
+    ImportAccounts {
+        /// Format of the file
+        #[arg(long, value_parser = parse_account_file_format)]
+        format: AccountFileFormat,
+        /// Only validate the file and print the report
+        #[arg(long)]
+        dry_run: bool,
+        /// Path of the file to import
+        file: PathBuf,
+    },
+    /// Export every account to a CSV or JSONL file
+    ExportAccounts {
+        /// Format of the file
+        #[arg(long, value_parser = parse_account_file_format)]
+        format: AccountFileFormat,
+        /// Path of the file to write
+        #[arg(long)]
+        output: PathBuf,
+        /// Also write the password hashes, needed to import the accounts elsewhere
+        #[arg(long)]
+        include_password_hashes: bool,
+    },
+    /// Create an account in the administrator group
+    CreateAdmin {
+        #[arg(long)]
+        handle: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        country_code: String,
+        /// Password of the account, a random one is generated and printed if not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Move an account into the administrator group, or another one
+    Promote {
+        /// Handle of the account
+        handle: String,
+        #[arg(long, default_value = ADMINISTRATOR_GROUP)]
+        group: String,
+    },
+    /// Move an account back into the default group, or another one
+    Demote {
+        /// Handle of the account
+        handle: String,
+        #[arg(long)]
+        group: Option<String>,
+    },
+    /// Run the database migrations without starting the server
+    Migrate,
+    /// Revoke every session of an account
+    RevokeSessions {
+        /// Handle of the account
+        handle: String,
+    },
+    /// Set a new password for an account and revoke its sessions
+    ResetPassword {
+        /// Handle of the account
+        handle: String,
+        /// New password, a random one is generated and printed if not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+}
+
+fn parse_account_file_format(format: &str) -> std::result::Result<AccountFileFormat, String> {
+    format
+        .parse::<AccountFileFormat>()
+        .map_err(|err| err.to_string())
+}
+
+pub async fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::ImportAccounts {
+            format,
+            dry_run,
+            file,
+        } => {
+            let data = async_std::fs::read_to_string(&file)
+                .await
+                .map_err(|err| Error::Import(ImportError::ReadFile(err.to_string())))?;
+
+            let report =
+                import_accounts(format, &data, dry_run, None, CLI_ACTOR_ID, CLI_IP_ADDRESS).await?;
+
+            print_import_report(&report)?;
+        }
+        Command::ExportAccounts {
+            format,
+            output,
+            include_password_hashes,
+        } => {
+            let data = export_accounts(
+                format,
+                include_password_hashes,
+                CLI_ACTOR_ID,
+                CLI_IP_ADDRESS,
+            )
+            .await?;
+
+            async_std::fs::write(&output, data)
+                .await
+                .map_err(|err| Error::Import(ImportError::WriteFile(err.to_string())))?;
+        }
+        Command::CreateAdmin {
+            handle,
+            name,
+            email,
+            country_code,
+            password,
+        } => {
+            let (password, generated) = password_or_generated(password);
+
+            let account = ImportedAccount {
+                handle,
+                name,
+                email,
+                password,
+                group: Some(ADMINISTRATOR_GROUP.to_owned()),
+                gender: Some(Gender::NotSpecified),
+                email_is_public: None,
+                gender_is_public: None,
+                country_code,
+                created_at: None,
+            };
+
+            let report = import_account(account, CLI_ACTOR_ID, CLI_IP_ADDRESS).await?;
+
+            print_import_report(&report)?;
+            print_generated_password(generated);
+        }
+        Command::Promote { handle, group } => {
+            change_group(&handle, &group).await?;
+        }
+        Command::Demote { handle, group } => {
+            let group = group.unwrap_or_else(|| CONFIG.default_group.to_owned());
+
+            change_group(&handle, &group).await?;
+        }
+        Command::Migrate => {
+            migrate!("./migrations")
+                .run(&*DATABASE_POOL)
+                .await
+                .map_err(|err| Error::Cli(CliError::Migrate(err.to_string())))?;
+        }
+        Command::RevokeSessions { handle } => {
+            let account_id = get_account_id_by_handle(&handle).await?;
+
+            let query = sqlx::query!(
+                r#"
+                    DELETE FROM sessions
+                    WHERE account_id = $1
+                "#,
+                account_id
+            );
+
+            let result = query
+                .execute(&*DATABASE_POOL)
+                .await
+                .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+            println!("Revoked {} sessions of @{}", result.rows_affected(), handle);
+        }
+        Command::ResetPassword { handle, password } => {
+            let (password, generated) = password_or_generated(password);
+
+            reset_password(&handle, &password).await?;
+
+            print_generated_password(generated);
+        }
+    }
+
+    Ok(())
+}
+
+fn password_or_generated(password: Option<String>) -> (String, Option<String>) {
+    match password {
+        Some(password) => (password, None),
+        None => {
+            let password = get_random_string(GENERATED_PASSWORD_LENGTH);
+            (password.to_owned(), Some(password))
+        }
+    }
+}
+
+fn print_generated_password(generated: Option<String>) {
+    if let Some(password) = generated {
+        println!("Generated password: {}", password);
+    }
+}
+
+fn print_import_report(report: &ImportReport) -> Result<()> {
+    let report_json = serde_json::to_string_pretty(report)
+        .map_err(|err| Error::Import(ImportError::Serialize(err.to_string())))?;
+
+    println!("{}", report_json);
+
+    if !report.errors.is_empty() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+async fn get_account_id_by_handle(handle: &str) -> Result<String> {
+    let handle = sanitize_handle(handle)?;
+
+    let query = sqlx::query!(
+        r#"
+            SELECT id
+            FROM accounts
+            WHERE handle = $1
+        "#,
+        handle
+    );
+
+    let result = query
+        .fetch_optional(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    match result {
+        Some(result) => Ok(result.id),
+        None => Err(Error::Cli(CliError::AccountNotFound(handle))),
+    }
+}
+
+async fn change_group(handle: &str, group: &str) -> Result<()> {
+    let handle = sanitize_handle(handle)?;
+
+    let mut transaction = DATABASE_POOL
+        .begin()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    // CHECK THAT THE GROUP EXISTS
+
+    let query = sqlx::query!(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM roles WHERE name = $1
+            ) AS "group_exists!"
+        "#,
+        group
+    );
+
+    let result = query
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    if !result.group_exists {
+        return Err(Error::Cli(CliError::GroupNotFound(group.to_owned())));
+    }
+
+    // GET THE CURRENT GROUP FOR THE AUDIT LOG
+
+    let query = sqlx::query!(
+        r#"
+            SELECT id, "group"
+            FROM accounts
+            WHERE handle = $1
+            FOR UPDATE
+        "#,
+        handle
+    );
+
+    let account = query
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .ok_or_else(|| Error::Cli(CliError::AccountNotFound(handle.to_owned())))?;
+
+    // CHANGE THE GROUP
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE accounts
+            SET "group" = $1
+            WHERE id = $2
+        "#,
+        group,
+        account.id
+    );
+
+    query
+        .execute(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: CLI_ACTOR_ID.to_owned(),
+            target_id: account.id,
+            action: AuditAction::GroupChange,
+            diff: json!({
+                "before": { "group": account.group },
+                "after": { "group": group },
+            }),
+            ip_address: CLI_IP_ADDRESS.to_owned(),
+        },
+    )
+    .await?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    println!("Moved @{} from {} to {}", handle, account.group, group);
+
+    Ok(())
+}
+
+async fn reset_password(handle: &str, password: &str) -> Result<()> {
+    let handle = sanitize_handle(handle)?;
+
+    let encrypted_password = encryption::encrypt_string(password)?;
+
+    let mut transaction = DATABASE_POOL
+        .begin()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    // SET THE NEW PASSWORD
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE accounts
+            SET password = $1
+            WHERE handle = $2
+            RETURNING id
+        "#,
+        encrypted_password,
+        handle
+    );
+
+    let account_id = query
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .ok_or_else(|| Error::Cli(CliError::AccountNotFound(handle.to_owned())))?
+        .id;
+
+    // REVOKE ALL LIVE SESSIONS SO THE OLD PASSWORD CAN'T BE USED ANYMORE
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM sessions
+            WHERE account_id = $1
+        "#,
+        account_id
+    );
+
+    query
+        .execute(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: CLI_ACTOR_ID.to_owned(),
+            target_id: account_id,
+            action: AuditAction::PasswordChange,
+            diff: json!({
+                "before": { "password": "[redacted]" },
+                "after": { "password": "[redacted]" },
+            }),
+            ip_address: CLI_IP_ADDRESS.to_owned(),
+        },
+    )
+    .await?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    println!("Reset the password of @{}", handle);
+
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::config::CONFIG;
+use ipnet::IpNet;
+use lazy_static::lazy_static;
+use std::net::{IpAddr, SocketAddr};
+
+lazy_static! {
+    static ref TRUSTED_PROXIES: Vec<IpNet> = CONFIG
+        .trusted_proxies
+        .split(',')
+        .map(|proxy| proxy.trim())
+        .filter(|proxy| !proxy.is_empty())
+        .filter_map(|proxy| match proxy.parse::<IpNet>() {
+            Ok(network) => Some(network),
+            Err(_) => match proxy.parse::<IpAddr>() {
+                Ok(ip_address) => Some(IpNet::from(ip_address)),
+                Err(_) => {
+                    log::warn!("Ignoring invalid trusted proxy {}", proxy);
+                    None
+                }
+            },
+        })
+        .collect();
+}
+
+fn parse_node(node: &str) -> Option<IpAddr> {
+    // NODES MAY BE QUOTED, HAVE A PORT OR BE AN IPV6 ADDRESS IN BRACKETS
+    // E.G. 192.0.2.43, "192.0.2.43:47011" OR "[2001:db8:cafe::17]:4711"
+
+    let node = node.trim().trim_matches('"');
+
+    if let Some(bracketed) = node.strip_prefix('[') {
+        return bracketed.split(']').next()?.parse().ok();
+    }
+
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|address| address.ip()))
+}
+
+fn get_forwarded_nodes(req: &tide::Request<()>) -> Option<Vec<String>> {
+    // PREFER THE STANDARD FORWARDED HEADER (RFC 7239)
+    // AND FALL BACK TO X-FORWARDED-FOR
+
+    if let Some(values) = req.header("Forwarded") {
+        let nodes = values
+            .iter()
+            .flat_map(|value| value.as_str().split(','))
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+
+                    if key.trim().eq_ignore_ascii_case("for") {
+                        Some(value.trim().to_string())
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect();
+
+        return Some(nodes);
+    }
+
+    let values = req.header("X-Forwarded-For")?;
+
+    let nodes = values
+        .iter()
+        .flat_map(|value| value.as_str().split(','))
+        .map(|node| node.trim().to_string())
+        .filter(|node| !node.is_empty())
+        .collect();
+
+    Some(nodes)
+}
+
+// Walks the forwarded chain from the right, skipping our own proxies,
+// the first address that isn't one of them is the client
+fn resolve_client_ip_address(
+    peer_address: IpAddr,
+    nodes: Option<Vec<String>>,
+    trusted_proxies: &[IpNet],
+) -> IpAddr {
+    let is_trusted = |ip_address: &IpAddr| {
+        trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip_address))
+    };
+
+    // ONLY TRUSTED PROXIES ARE ALLOWED TO TELL US WHO THE CLIENT IS
+
+    if !is_trusted(&peer_address) {
+        return peer_address;
+    }
+
+    let nodes = match nodes {
+        Some(nodes) => nodes,
+        None => return peer_address,
+    };
+
+    let mut client_address = peer_address;
+
+    for node in nodes.iter().rev() {
+        // AN ENTRY THAT DOESN'T PARSE CAN'T BE TRUSTED NOR REPORTED,
+        // SO THE ADDRESS THAT ACTUALLY CONNECTED TO US IS USED INSTEAD
+
+        let node_address = match parse_node(node) {
+            Some(node_address) => node_address,
+            None => return peer_address,
+        };
+
+        client_address = node_address;
+
+        if !is_trusted(&node_address) {
+            break;
+        }
+    }
+
+    client_address
+}
+
+pub fn get_client_ip_address(req: &tide::Request<()>) -> String {
+    // START FROM THE ADDRESS THAT ACTUALLY CONNECTED TO US
+
+    let peer_address = req
+        .peer_addr()
+        .and_then(parse_node)
+        .unwrap_or(IpAddr::from([127, 0, 0, 1]));
+
+    resolve_client_ip_address(peer_address, get_forwarded_nodes(req), &TRUSTED_PROXIES).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted_proxies() -> Vec<IpNet> {
+        vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "192.0.2.1/32".parse().unwrap(),
+        ]
+    }
+
+    fn nodes(nodes: &[&str]) -> Option<Vec<String>> {
+        Some(nodes.iter().map(|node| node.to_string()).collect())
+    }
+
+    #[test]
+    fn peer_address_is_used_without_forwarded_header() {
+        let peer_address = IpAddr::from([10, 0, 0, 1]);
+
+        let client_address = resolve_client_ip_address(peer_address, None, &trusted_proxies());
+
+        assert_eq!(client_address, peer_address);
+    }
+
+    #[test]
+    fn first_address_is_used_when_every_hop_is_trusted() {
+        let client_address = resolve_client_ip_address(
+            IpAddr::from([10, 0, 0, 1]),
+            nodes(&["10.0.0.3", "192.0.2.1", "10.0.0.2"]),
+            &trusted_proxies(),
+        );
+
+        assert_eq!(client_address, IpAddr::from([10, 0, 0, 3]));
+    }
+
+    #[test]
+    fn walk_stops_at_the_first_untrusted_hop() {
+        let client_address = resolve_client_ip_address(
+            IpAddr::from([10, 0, 0, 1]),
+            nodes(&["203.0.113.7", "198.51.100.4", "10.0.0.2"]),
+            &trusted_proxies(),
+        );
+
+        assert_eq!(client_address, IpAddr::from([198, 51, 100, 4]));
+    }
+
+    #[test]
+    fn unparsable_hop_falls_back_to_the_peer_address() {
+        let peer_address = IpAddr::from([10, 0, 0, 1]);
+
+        let client_address = resolve_client_ip_address(
+            peer_address,
+            nodes(&["203.0.113.7", "unknown", "10.0.0.2"]),
+            &trusted_proxies(),
+        );
+
+        assert_eq!(client_address, peer_address);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::models::{DkimAlgorithm, EmailTransportKind};
 use envconfig::Envconfig;
 use lazy_static::lazy_static;
 
@@ -15,8 +16,53 @@ pub struct Config {
     #[envconfig(from = "NAME_MAX_LENGTH")]
     pub name_max_length: usize,
 
-    #[envconfig(from = "VERIFICATION_CODE_LENGTH")]
-    pub verification_code_length: usize,
+    #[envconfig(from = "ACCOUNT_CREATION_CODE_ALPHABET", default = "0123456789")]
+    pub account_creation_code_alphabet: String,
+
+    #[envconfig(from = "ACCOUNT_CREATION_CODE_LENGTH", default = "6")]
+    pub account_creation_code_length: usize,
+
+    #[envconfig(from = "ACCOUNT_CREATION_CODE_TTL_SECONDS", default = "3600")]
+    pub account_creation_code_ttl_seconds: i64,
+
+    #[envconfig(from = "EMAIL_CHANGE_CODE_ALPHABET", default = "0123456789")]
+    pub email_change_code_alphabet: String,
+
+    #[envconfig(from = "EMAIL_CHANGE_CODE_LENGTH", default = "6")]
+    pub email_change_code_length: usize,
+
+    #[envconfig(from = "EMAIL_CHANGE_CODE_TTL_SECONDS", default = "900")]
+    pub email_change_code_ttl_seconds: i64,
+
+    #[envconfig(from = "PASSWORD_CHANGE_CODE_ALPHABET", default = "0123456789")]
+    pub password_change_code_alphabet: String,
+
+    #[envconfig(from = "PASSWORD_CHANGE_CODE_LENGTH", default = "6")]
+    pub password_change_code_length: usize,
+
+    #[envconfig(from = "PASSWORD_CHANGE_CODE_TTL_SECONDS", default = "900")]
+    pub password_change_code_ttl_seconds: i64,
+
+    #[envconfig(from = "FORGOT_PASSWORD_CODE_ALPHABET", default = "0123456789")]
+    pub forgot_password_code_alphabet: String,
+
+    #[envconfig(from = "FORGOT_PASSWORD_CODE_LENGTH", default = "6")]
+    pub forgot_password_code_length: usize,
+
+    #[envconfig(from = "FORGOT_PASSWORD_CODE_TTL_SECONDS", default = "900")]
+    pub forgot_password_code_ttl_seconds: i64,
+
+    #[envconfig(from = "ACCOUNT_DELETION_CODE_ALPHABET", default = "0123456789")]
+    pub account_deletion_code_alphabet: String,
+
+    #[envconfig(from = "ACCOUNT_DELETION_CODE_LENGTH", default = "6")]
+    pub account_deletion_code_length: usize,
+
+    #[envconfig(from = "ACCOUNT_DELETION_CODE_TTL_SECONDS", default = "900")]
+    pub account_deletion_code_ttl_seconds: i64,
+
+    #[envconfig(from = "VERIFICATION_RESEND_COOLDOWN_SECONDS", default = "60")]
+    pub verification_resend_cooldown_seconds: i64,
 
     #[envconfig(from = "ENCRYPTION_PROCESSING_COST")]
     pub encryption_processing_cost: u32,
@@ -30,12 +76,54 @@ pub struct Config {
     #[envconfig(from = "ACCOUNT_ID_LENGTH")]
     pub account_id_length: usize,
 
+    #[envconfig(from = "LOGIN_EVENT_ID_LENGTH", default = "32")]
+    pub login_event_id_length: usize,
+
+    #[envconfig(from = "DEFAULT_GROUP", default = "default")]
+    pub default_group: String,
+
+    #[envconfig(from = "ACCOUNT_DELETION_GRACE_PERIOD_DAYS", default = "30")]
+    pub account_deletion_grace_period_days: i64,
+
+    #[envconfig(from = "ACCOUNT_PURGE_INTERVAL_SECONDS", default = "3600")]
+    pub account_purge_interval_seconds: u64,
+
+    #[envconfig(from = "DATA_EXPORT_ID_LENGTH", default = "32")]
+    pub data_export_id_length: usize,
+
+    #[envconfig(from = "DATA_EXPORT_EXPIRY_HOURS", default = "48")]
+    pub data_export_expiry_hours: i64,
+
+    #[envconfig(from = "EMAIL_CHANGE_REVERT_TOKEN_LENGTH", default = "48")]
+    pub email_change_revert_token_length: usize,
+
+    #[envconfig(from = "EMAIL_CHANGE_REVERT_HOURS", default = "72")]
+    pub email_change_revert_hours: i64,
+
+    #[envconfig(from = "IMPERSONATION_SESSION_MINUTES", default = "15")]
+    pub impersonation_session_minutes: i64,
+
+    #[envconfig(from = "INVITE_ONLY_REGISTRATION", default = "false")]
+    pub invite_only_registration: bool,
+
+    #[envconfig(from = "INVITE_CODE_LENGTH", default = "16")]
+    pub invite_code_length: usize,
+
+    #[envconfig(from = "INVITE_QUOTA_PER_ACCOUNT", default = "0")]
+    pub invite_quota_per_account: i64,
+
     #[envconfig(from = "DEVICE_NAME_MAX_LENGTH")]
     pub device_name_max_length: usize,
 
     #[envconfig(from = "DEVICE_DESCRIPTION_MAX_LENGTH")]
     pub device_description_max_length: usize,
 
+    #[envconfig(from = "TRUSTED_PROXIES", default = "")]
+    pub trusted_proxies: String,
+
+    #[envconfig(from = "GEOLOCATION_DATABASE_PATH")]
+    pub geolocation_database_path: Option<String>,
+
     #[envconfig(from = "PICTURE_MAX_DIMENTION")]
     pub picture_max_dimention: u32,
 
@@ -72,6 +160,27 @@ pub struct Config {
     #[envconfig(from = "EMAIL_USERNAME")]
     pub email_username: String,
 
+    #[envconfig(from = "EMAIL_TRANSPORT", default = "smtp")]
+    pub email_transport: EmailTransportKind,
+
+    #[envconfig(from = "SENDMAIL_COMMAND")]
+    pub sendmail_command: Option<String>,
+
+    #[envconfig(from = "EMAIL_FILE_DIRECTORY", default = "emails")]
+    pub email_file_directory: String,
+
+    #[envconfig(from = "DKIM_PRIVATE_KEY_PATH")]
+    pub dkim_private_key_path: Option<String>,
+
+    #[envconfig(from = "DKIM_SELECTOR")]
+    pub dkim_selector: Option<String>,
+
+    #[envconfig(from = "DKIM_DOMAIN")]
+    pub dkim_domain: Option<String>,
+
+    #[envconfig(from = "DKIM_ALGORITHM", default = "rsa")]
+    pub dkim_algorithm: DkimAlgorithm,
+
     #[envconfig(from = "SMTP_RELAY")]
     pub smtp_relay: String,
 
@@ -87,68 +196,44 @@ pub struct Config {
     #[envconfig(from = "SMTP_PASSWORD")]
     pub smtp_password: String,
 
-    #[envconfig(from = "EMAIL_PLACEHOLDER_MARKER")]
-    pub email_placeholder_marker: String,
-
-    #[envconfig(from = "SERVICE_STARTUP_EMAIL_HTML")]
-    pub service_startup_email_html: bool,
-
-    #[envconfig(from = "SERVICE_STARTUP_EMAIL_SUBJECT")]
-    pub service_startup_email_subject: String,
-
-    #[envconfig(from = "SERVICE_STARTUP_EMAIL_BODY")]
-    pub service_startup_email_body: String,
-
-    #[envconfig(from = "ACCOUNT_CREATION_VERIFICATION_EMAIL_HTML")]
-    pub account_creation_verification_email_html: bool,
-
-    #[envconfig(from = "ACCOUNT_CREATION_VERIFICATION_EMAIL_SUBJECT")]
-    pub account_creation_verification_email_subject: String,
-
-    #[envconfig(from = "ACCOUNT_CREATION_VERIFICATION_EMAIL_BODY")]
-    pub account_creation_verification_email_body: String,
-
-    #[envconfig(from = "ACCOUNT_EMAIL_CHANGE_ORIGINAL_EMAIL_VERIFICATION_HTML")]
-    pub account_email_change_original_email_verification_html: bool,
-
-    #[envconfig(from = "ACCOUNT_EMAIL_CHANGE_ORIGINAL_EMAIL_VERIFICATION_SUBJECT")]
-    pub account_email_change_original_email_verification_subject: String,
+    #[envconfig(from = "PASSWORD_CHANGE_NOTIFICATION_EMAIL", default = "true")]
+    pub password_change_notification_email: bool,
 
-    #[envconfig(from = "ACCOUNT_EMAIL_CHANGE_ORIGINAL_EMAIL_VERIFICATION_BODY")]
-    pub account_email_change_original_email_verification_body: String,
+    #[envconfig(from = "GROUP_CHANGE_NOTIFICATION_EMAIL", default = "true")]
+    pub group_change_notification_email: bool,
 
-    #[envconfig(from = "ACCOUNT_EMAIL_CHANGE_NEW_EMAIL_VERIFICATION_HTML")]
-    pub account_email_change_new_email_verification_html: bool,
+    #[envconfig(from = "ACCOUNT_DELETION_NOTIFICATION_EMAIL", default = "true")]
+    pub account_deletion_notification_email: bool,
 
-    #[envconfig(from = "ACCOUNT_EMAIL_CHANGE_NEW_EMAIL_VERIFICATION_SUBJECT")]
-    pub account_email_change_new_email_verification_subject: String,
+    #[envconfig(from = "EMAIL_TEMPLATES_DIRECTORY", default = "templates/email")]
+    pub email_templates_directory: String,
 
-    #[envconfig(from = "ACCOUNT_EMAIL_CHANGE_NEW_EMAIL_VERIFICATION_BODY")]
-    pub account_email_change_new_email_verification_body: String,
+    #[envconfig(from = "DEFAULT_LOCALE", default = "en")]
+    pub default_locale: String,
 
-    #[envconfig(from = "ACCOUNT_PASSWORD_CHANGE_VERIFICATION_HTML")]
-    pub account_password_change_verification_html: bool,
+    #[envconfig(from = "EMAIL_OUTBOX_POLL_INTERVAL_SECONDS", default = "5")]
+    pub email_outbox_poll_interval_seconds: u64,
 
-    #[envconfig(from = "ACCOUNT_PASSWORD_CHANGE_VERIFICATION_SUBJECT")]
-    pub account_password_change_verification_subject: String,
+    #[envconfig(from = "EMAIL_OUTBOX_BATCH_SIZE", default = "20")]
+    pub email_outbox_batch_size: i64,
 
-    #[envconfig(from = "ACCOUNT_FORGOT_PASSWORD_VERIFICATION_HTML")]
-    pub account_forgot_password_verification_html: bool,
+    #[envconfig(from = "EMAIL_OUTBOX_MAX_ATTEMPTS", default = "8")]
+    pub email_outbox_max_attempts: i32,
 
-    #[envconfig(from = "ACCOUNT_FORGOT_PASSWORD_VERIFICATION_SUBJECT")]
-    pub account_forgot_password_verification_subject: String,
+    #[envconfig(from = "EMAIL_OUTBOX_RETRY_BASE_SECONDS", default = "30")]
+    pub email_outbox_retry_base_seconds: i64,
 
-    #[envconfig(from = "ACCOUNT_FORGOT_PASSWORD_VERIFICATION_BODY")]
-    pub account_forgot_password_verification_body: String,
+    #[envconfig(from = "EMAIL_OUTBOX_RETENTION_DAYS", default = "7")]
+    pub email_outbox_retention_days: i64,
 
-    #[envconfig(from = "ACCOUNT_DELETION_VERIFICATION_HTML")]
-    pub account_deletion_verification_html: bool,
+    #[envconfig(from = "SESSION_REVOKE_LINK")]
+    pub session_revoke_link: String,
 
-    #[envconfig(from = "ACCOUNT_DELETION_VERIFICATION_SUBJECT")]
-    pub account_deletion_verification_subject: String,
+    #[envconfig(from = "DATA_EXPORT_DOWNLOAD_LINK")]
+    pub data_export_download_link: String,
 
-    #[envconfig(from = "ACCOUNT_DELETION_VERIFICATION_BODY")]
-    pub account_deletion_verification_body: String,
+    #[envconfig(from = "EMAIL_CHANGE_REVERT_LINK")]
+    pub email_change_revert_link: String,
 }
 
 lazy_static! {
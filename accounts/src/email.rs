@@ -1,12 +1,145 @@
 use crate::config::CONFIG;
-use crate::error::{EmailError, Error};
+use crate::email_transport::EMAIL_TRANSPORT;
+use crate::error::{DatabaseError, EmailError, Error};
+use crate::models::{DkimAlgorithm, EmailTemplate};
 use crate::prelude::*;
+use crate::templates::render_email;
+use chrono::Utc;
+use lazy_static::lazy_static;
 use lettre::address::AddressError;
-use lettre::message::header::ContentType;
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Message, SmtpTransport, Transport};
+use lettre::message::dkim::{
+    DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningAlgorithm,
+    DkimSigningKey,
+};
+use lettre::message::header::{ContentType, HeaderName};
+use lettre::message::MultiPart;
+use lettre::Message;
+use sqlx::PgConnection;
+use tera::Context;
 
-pub fn send_email(recipient: &str, subject: &str, body: &str, html: bool) -> Result<()> {
+// Signing only happens when both DKIM_PRIVATE_KEY_PATH and DKIM_SELECTOR are set
+lazy_static! {
+    pub static ref DKIM_CONFIG: Option<DkimConfig> = load_dkim_config().unwrap();
+}
+
+fn load_dkim_config() -> Result<Option<DkimConfig>> {
+    let (key_path, selector) = match (&CONFIG.dkim_private_key_path, &CONFIG.dkim_selector) {
+        (Some(key_path), Some(selector)) => (key_path, selector),
+        _ => return Ok(None),
+    };
+
+    let private_key = std::fs::read_to_string(key_path)
+        .map_err(|err| Error::Email(EmailError::LoadDkimKey(err.to_string())))?;
+
+    // SIGN FOR THE DOMAIN OF THE SENDER ADDRESS UNLESS TOLD OTHERWISE
+
+    let domain = CONFIG.dkim_domain.to_owned().unwrap_or_else(|| {
+        CONFIG
+            .email_address
+            .rsplit('@')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    });
+
+    let dkim_config =
+        build_dkim_config(selector, &domain, private_key.trim(), CONFIG.dkim_algorithm)?;
+
+    Ok(Some(dkim_config))
+}
+
+pub fn build_dkim_config(
+    selector: &str,
+    domain: &str,
+    private_key: &str,
+    algorithm: DkimAlgorithm,
+) -> Result<DkimConfig> {
+    let algorithm = match algorithm {
+        DkimAlgorithm::Rsa => DkimSigningAlgorithm::Rsa,
+        DkimAlgorithm::Ed25519 => DkimSigningAlgorithm::Ed25519,
+    };
+
+    let signing_key = DkimSigningKey::new(private_key, algorithm)
+        .map_err(|err| Error::Email(EmailError::LoadDkimKey(err.to_string())))?;
+
+    // RELAXED CANONICALIZATION SURVIVES RELAYS THAT REWRAP HEADERS OR TRIM WHITESPACE
+
+    Ok(DkimConfig::new(
+        selector.to_string(),
+        domain.to_string(),
+        signing_key,
+        vec![
+            HeaderName::new_from_ascii_str("From"),
+            HeaderName::new_from_ascii_str("To"),
+            HeaderName::new_from_ascii_str("Subject"),
+            HeaderName::new_from_ascii_str("Date"),
+        ],
+        DkimCanonicalization {
+            header: DkimCanonicalizationType::Relaxed,
+            body: DkimCanonicalizationType::Relaxed,
+        },
+    ))
+}
+
+pub fn send_templated_email(
+    recipient: &str,
+    template: EmailTemplate,
+    locale: Option<&str>,
+    context: &Context,
+) -> Result<()> {
+    let email = render_email(template, locale, context)?;
+
+    send_email(
+        recipient,
+        &email.subject,
+        &email.text,
+        email.html.as_deref(),
+    )
+}
+
+// Queued emails are only delivered by the outbox worker once the
+// transaction they were queued in commits
+pub async fn queue_templated_email(
+    connection: &mut PgConnection,
+    recipient: &str,
+    template: EmailTemplate,
+    locale: Option<&str>,
+    context: &Context,
+) -> Result<()> {
+    let email = render_email(template, locale, context)?;
+
+    let created_at = Utc::now().naive_utc();
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO email_outbox (
+                "recipient",
+                "subject",
+                "text_body",
+                "html_body",
+                "next_attempt_at",
+                "created_at"
+            )
+            VALUES ($1, $2, $3, $4, $5, $5)
+        "#,
+        recipient,
+        email.subject,
+        email.text,
+        email.html,
+        created_at
+    );
+
+    query
+        .execute(&mut *connection)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+// Emails with an HTML version are sent as multipart/alternative
+// so clients that can't or won't render HTML fall back to the text
+pub fn send_email(recipient: &str, subject: &str, text: &str, html: Option<&str>) -> Result<()> {
     let sending = f!("{} <{}>", &CONFIG.email_username, &CONFIG.email_address)
         .parse()
         .map_err(|err: AddressError| {
@@ -17,37 +150,127 @@ pub fn send_email(recipient: &str, subject: &str, body: &str, html: bool) -> Res
         Error::Email(EmailError::ParseRecipientAddress(err.to_string()))
     })?;
 
-    let mut email_builder = Message::builder()
+    let email_builder = Message::builder()
         .from(sending)
         .to(recieving_address)
         .subject(subject);
 
-    if html {
-        email_builder = email_builder.header(ContentType::TEXT_HTML);
+    let mut email = match html {
+        Some(html) => email_builder.multipart(MultiPart::alternative_plain_html(
+            text.to_string(),
+            html.to_string(),
+        )),
+        None => email_builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(text.to_string()),
     }
+    .map_err(|err| Error::Email(EmailError::Build(err.to_string())))?;
 
-    let email = email_builder
-        .body(body.to_string())
-        .map_err(|err| Error::Email(EmailError::Build(err.to_string())))?;
+    // SIGN LAST, ANY HEADER ADDED AFTERWARDS WOULD BREAK THE SIGNATURE
 
-    let creds = Credentials::new(
-        CONFIG.smtp_username.to_owned(),
-        CONFIG.smtp_password.to_owned(),
-    );
+    if let Some(dkim_config) = DKIM_CONFIG.as_ref() {
+        email.sign(dkim_config);
+    }
 
-    let mailer = if CONFIG.smtp_starttls {
-        SmtpTransport::starttls_relay(&CONFIG.smtp_relay)
-    } else {
-        SmtpTransport::relay(&CONFIG.smtp_relay)
+    EMAIL_TRANSPORT.send_message(&email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // base64 of the bytes 0 to 31, a throwaway Ed25519 seed
+    const TEST_ED25519_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    // base64(sha256("Your verification code is 123456\r\n"))
+    const TEST_BODY_HASH: &str = "pDGq6Jd/F6+dYnrMSu7yDbDK7xk0kCc3dt7ubTFHqsM=";
+
+    fn build_test_email(body: &str) -> Message {
+        Message::builder()
+            .from("Accounts <noreply@accounts.pt>".parse().unwrap())
+            .to("someone@accounts.pt".parse().unwrap())
+            .subject("Verification code")
+            .body(body.to_string())
+            .unwrap()
     }
-    .map_err(|err| Error::Email(EmailError::StartRelayConnection(err.to_string())))?
-    .credentials(creds)
-    .port(CONFIG.smtp_port)
-    .build();
 
-    mailer
-        .send(&email)
-        .map_err(|err| Error::Email(EmailError::Send(err.to_string())))?;
+    fn sign_and_get_signature_tags(mut email: Message) -> HashMap<String, String> {
+        let dkim_config = build_dkim_config(
+            "accounts",
+            "accounts.pt",
+            TEST_ED25519_KEY,
+            DkimAlgorithm::Ed25519,
+        )
+        .unwrap();
 
-    Ok(())
+        email.sign(&dkim_config);
+
+        // UNFOLD THE HEADERS AND PICK THE SIGNATURE ONE
+
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        let unfolded = formatted.replace("\r\n ", " ").replace("\r\n\t", " ");
+
+        let signature = unfolded
+            .split("\r\n")
+            .find_map(|line| line.strip_prefix("DKIM-Signature:"))
+            .expect("missing DKIM-Signature header");
+
+        signature
+            .split(';')
+            .filter_map(|tag| tag.split_once('='))
+            .map(|(name, value)| {
+                (
+                    name.trim().to_string(),
+                    value.chars().filter(|c| !c.is_whitespace()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn signature_header_describes_the_signing_setup() {
+        let tags =
+            sign_and_get_signature_tags(build_test_email("Your verification code is 123456"));
+
+        assert_eq!(tags["v"], "1");
+        assert_eq!(tags["a"], "ed25519-sha256");
+        assert_eq!(tags["d"], "accounts.pt");
+        assert_eq!(tags["s"], "accounts");
+        assert_eq!(tags["c"], "relaxed/relaxed");
+
+        let signed_headers: Vec<String> = tags["h"]
+            .split(':')
+            .map(|header| header.to_lowercase())
+            .collect();
+
+        for header in ["from", "to", "subject", "date"] {
+            assert!(signed_headers.contains(&header.to_string()));
+        }
+
+        assert!(!tags["b"].is_empty());
+    }
+
+    #[test]
+    fn signature_body_hash_matches_relaxed_canonical_body() {
+        let tags =
+            sign_and_get_signature_tags(build_test_email("Your verification code is 123456"));
+
+        assert_eq!(tags["bh"], TEST_BODY_HASH);
+
+        // EXTRA WHITESPACE AND TRAILING EMPTY LINES DON'T CHANGE A RELAXED BODY HASH
+
+        let tags = sign_and_get_signature_tags(build_test_email(
+            "Your  verification code is 123456  \r\n\r\n",
+        ));
+
+        assert_eq!(tags["bh"], TEST_BODY_HASH);
+    }
+
+    #[test]
+    fn invalid_private_key_is_rejected() {
+        let result = build_dkim_config("accounts", "accounts.pt", "not a key", DkimAlgorithm::Rsa);
+
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,201 @@
+use crate::{
+    config::CONFIG,
+    error::{EmailError, Error},
+    models::EmailTransportKind,
+    prelude::*,
+};
+use lazy_static::lazy_static;
+use lettre::{
+    transport::smtp::authentication::Credentials, FileTransport, Message, SendmailTransport,
+    SmtpTransport, Transport,
+};
+use std::sync::Mutex;
+
+// Every email leaves the service through the transport picked by EMAIL_TRANSPORT,
+// "file" and "memory" let development and tests run without a mail server
+
+pub trait EmailTransport: Send + Sync {
+    fn send_message(&self, email: &Message) -> Result<()>;
+}
+
+lazy_static! {
+    pub static ref EMAIL_TRANSPORT: Box<dyn EmailTransport> = build_email_transport().unwrap();
+    static ref CAPTURED_EMAILS: Mutex<Vec<CapturedEmail>> = Mutex::new(Vec::new());
+}
+
+fn build_email_transport() -> Result<Box<dyn EmailTransport>> {
+    let transport: Box<dyn EmailTransport> = match CONFIG.email_transport {
+        EmailTransportKind::Smtp => {
+            let creds = Credentials::new(
+                CONFIG.smtp_username.to_owned(),
+                CONFIG.smtp_password.to_owned(),
+            );
+
+            let mailer = if CONFIG.smtp_starttls {
+                SmtpTransport::starttls_relay(&CONFIG.smtp_relay)
+            } else {
+                SmtpTransport::relay(&CONFIG.smtp_relay)
+            }
+            .map_err(|err| Error::Email(EmailError::StartRelayConnection(err.to_string())))?
+            .credentials(creds)
+            .port(CONFIG.smtp_port)
+            .build();
+
+            Box::new(mailer)
+        }
+        EmailTransportKind::Sendmail => match &CONFIG.sendmail_command {
+            Some(command) => Box::new(SendmailTransport::new_with_command(command)),
+            None => Box::new(SendmailTransport::new()),
+        },
+        EmailTransportKind::File => {
+            std::fs::create_dir_all(&CONFIG.email_file_directory)
+                .map_err(|err| Error::Email(EmailError::CreateDirectory(err.to_string())))?;
+
+            Box::new(FileTransport::new(&CONFIG.email_file_directory))
+        }
+        EmailTransportKind::Memory => Box::new(MemoryTransport),
+    };
+
+    Ok(transport)
+}
+
+impl EmailTransport for SmtpTransport {
+    fn send_message(&self, email: &Message) -> Result<()> {
+        self.send(email)
+            .map_err(|err| Error::Email(EmailError::Send(err.to_string())))?;
+
+        Ok(())
+    }
+}
+
+impl EmailTransport for SendmailTransport {
+    fn send_message(&self, email: &Message) -> Result<()> {
+        self.send(email)
+            .map_err(|err| Error::Email(EmailError::Send(err.to_string())))?;
+
+        Ok(())
+    }
+}
+
+// Writes one "<id>.eml" file per message
+impl EmailTransport for FileTransport {
+    fn send_message(&self, email: &Message) -> Result<()> {
+        self.send(email)
+            .map_err(|err| Error::Email(EmailError::Send(err.to_string())))?;
+
+        Ok(())
+    }
+}
+
+// Only read back by tests
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct CapturedEmail {
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub message: String,
+}
+
+// Keeps the messages in memory instead of sending them
+pub struct MemoryTransport;
+
+impl EmailTransport for MemoryTransport {
+    fn send_message(&self, email: &Message) -> Result<()> {
+        let envelope = email.envelope();
+
+        let captured_email = CapturedEmail {
+            from: envelope.from().map(|address| address.to_string()),
+            to: envelope
+                .to()
+                .iter()
+                .map(|address| address.to_string())
+                .collect(),
+            message: String::from_utf8_lossy(&email.formatted()).into_owned(),
+        };
+
+        CAPTURED_EMAILS
+            .lock()
+            .map_err(|err| Error::Email(EmailError::Send(err.to_string())))?
+            .push(captured_email);
+
+        Ok(())
+    }
+}
+
+// Returns and forgets the messages kept by the memory transport
+#[allow(dead_code)]
+pub fn take_captured_emails() -> Vec<CapturedEmail> {
+    CAPTURED_EMAILS
+        .lock()
+        .map(|mut emails| std::mem::take(&mut *emails))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{email::send_templated_email, models::EmailTemplate};
+    use std::sync::MutexGuard;
+    use tera::Context;
+
+    // THE CAPTURED EMAILS ARE SHARED, SO THE TESTS READING THEM TAKE TURNS
+
+    static CAPTURED_EMAILS_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock_captured_emails() -> MutexGuard<'static, ()> {
+        CAPTURED_EMAILS_LOCK
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    #[test]
+    fn memory_transport_captures_messages() {
+        let _lock = lock_captured_emails();
+
+        let email = Message::builder()
+            .from("Accounts <noreply@accounts.pt>".parse().unwrap())
+            .to("someone@accounts.pt".parse().unwrap())
+            .subject("Verification code")
+            .body("Your verification code is 123456".to_string())
+            .unwrap();
+
+        MemoryTransport.send_message(&email).unwrap();
+
+        let captured_emails = take_captured_emails();
+
+        assert_eq!(captured_emails.len(), 1);
+        assert_eq!(captured_emails[0].to, vec!["someone@accounts.pt"]);
+        assert!(captured_emails[0].message.contains("123456"));
+        assert!(take_captured_emails().is_empty());
+    }
+
+    #[test]
+    fn verification_code_can_be_read_from_the_sent_email() {
+        let _lock = lock_captured_emails();
+
+        // EVERYTHING ELSE IS CONFIGURED FROM THE TEMPLATE
+
+        std::env::set_var("EMAIL_TRANSPORT", "memory");
+        dotenv::from_path(concat!(env!("CARGO_MANIFEST_DIR"), "/../.env.template")).unwrap();
+
+        let mut context = Context::new();
+        context.insert("handle", "someone");
+        context.insert("verification_code", "K7Q2X9");
+
+        send_templated_email(
+            "someone@accounts.pt",
+            EmailTemplate::AccountCreationVerification,
+            Some("en"),
+            &context,
+        )
+        .unwrap();
+
+        let captured_emails = take_captured_emails();
+
+        assert_eq!(captured_emails.len(), 1);
+        assert_eq!(captured_emails[0].to, vec!["someone@accounts.pt"]);
+        assert!(captured_emails[0]
+            .message
+            .contains("Your verification code is: K7Q2X9"));
+    }
+}
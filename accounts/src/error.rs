@@ -12,6 +12,10 @@ pub enum EmailError {
     StartRelayConnection(String),
     #[error("Failed to send email")]
     Send(String),
+    #[error("Failed to create email directory")]
+    CreateDirectory(String),
+    #[error("Failed to load DKIM private key")]
+    LoadDkimKey(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -51,6 +55,12 @@ pub enum S3Error {
 
     #[error("Failed to put object in bucket: {0}")]
     PutObject(String),
+
+    #[error("Failed to delete object from bucket: {0}")]
+    DeleteObject(String),
+
+    #[error("Failed to get object from bucket: {0}")]
+    GetObject(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -58,10 +68,58 @@ pub enum DatabaseError {
     #[error("Failed to fetch row")]
     FetchOne(String),
 
+    #[error("Failed to fetch rows")]
+    FetchAll(String),
+
+    #[error("Failed to execute query")]
+    Execute(String),
+
     #[error("Row not found")]
     RowNotFound,
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("Failed to write archive: {0}")]
+    Write(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("Failed to read file: {0}")]
+    ReadFile(String),
+
+    #[error("Failed to write file: {0}")]
+    WriteFile(String),
+
+    #[error("Failed to serialize accounts: {0}")]
+    Serialize(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Failed to render template: {0}")]
+    Render(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("Account not found: {0}")]
+    AccountNotFound(String),
+
+    #[error("Group not found: {0}")]
+    GroupNotFound(String),
+
+    #[error("Failed to run migrations: {0}")]
+    Migrate(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerificationError {
+    #[error("Invalid verification code format for {0}")]
+    InvalidCodeFormat(String),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SanitizeError {
     #[error("Failed to sanitize handle")]
@@ -90,4 +148,19 @@ pub enum Error {
 
     #[error(transparent)]
     Sanitize(SanitizeError),
+
+    #[error(transparent)]
+    Archive(ArchiveError),
+
+    #[error(transparent)]
+    Import(ImportError),
+
+    #[error(transparent)]
+    Cli(CliError),
+
+    #[error(transparent)]
+    Template(TemplateError),
+
+    #[error(transparent)]
+    Verification(VerificationError),
 }
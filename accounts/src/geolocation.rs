@@ -0,0 +1,53 @@
+use crate::config::CONFIG;
+use lazy_static::lazy_static;
+use maxminddb::{geoip2, Reader};
+use std::net::IpAddr;
+
+pub struct Geolocation {
+    pub country_code: Option<String>,
+    pub city: Option<String>,
+}
+
+lazy_static! {
+    static ref GEOLOCATION_DATABASE: Option<Reader<Vec<u8>>> =
+        match CONFIG.geolocation_database_path.as_ref() {
+            Some(path) => match Reader::open_readfile(path) {
+                Ok(reader) => Some(reader),
+                Err(err) => {
+                    log::warn!("Failed to open geolocation database {}: {}", path, err);
+                    None
+                }
+            },
+            None => None,
+        };
+}
+
+pub fn locate_ip_address(ip_address: &str) -> Geolocation {
+    let mut geolocation = Geolocation {
+        country_code: None,
+        city: None,
+    };
+
+    // WITHOUT A DATABASE OR A VALID IP ADDRESS THE FIELDS STAY BLANK
+
+    let (Some(reader), Ok(ip_address)) = (
+        GEOLOCATION_DATABASE.as_ref(),
+        ip_address.trim().parse::<IpAddr>(),
+    ) else {
+        return geolocation;
+    };
+
+    if let Ok(city) = reader.lookup::<geoip2::City>(ip_address) {
+        geolocation.country_code = city
+            .country
+            .and_then(|country| country.iso_code)
+            .map(|iso_code| iso_code.to_lowercase());
+
+        geolocation.city = city
+            .city
+            .and_then(|city| city.names)
+            .and_then(|names| names.get("en").map(|name| name.to_string()));
+    }
+
+    geolocation
+}
@@ -3,43 +3,76 @@ use crate::{
     database::DATABASE_POOL,
     prelude::*,
     routes::{
-        change_email::{admin_email_change, begin_email_change, finish_email_change},
+        audit_log::{admin_get_audit_log, admin_verify_audit_log},
+        bulk::{admin_export_accounts, admin_import_accounts},
+        change_email::{
+            admin_email_change, begin_email_change, finish_email_change, resend_email_change,
+            revert_email_change,
+        },
         change_group::admin_group_change,
         change_info::info_change,
-        change_password::{admin_password_change, begin_password_change, finish_password_change},
-        create::{begin_account_creation, finish_account_creation},
-        delete::{admin_account_deletion, begin_account_deletion, finish_account_deletion},
-        get::{get_account, get_all_accounts, get_is_admin},
+        change_password::{
+            admin_password_change, begin_password_change, finish_password_change,
+            resend_forgot_password, resend_password_change,
+        },
+        create::{begin_account_creation, finish_account_creation, resend_account_creation},
+        data_export::{download_data_export, request_data_export},
+        delete::{
+            admin_account_deletion, begin_account_deletion, cancel_account_deletion,
+            finish_account_deletion, resend_account_deletion,
+        },
+        email_outbox::{admin_get_email_outbox, admin_retry_email_outbox_entry},
+        get::{get_account, get_all_accounts, get_is_admin, get_permissions},
+        impersonate::admin_impersonation,
+        invite::{create_invite, get_invites},
+        login_events::{admin_get_login_events, get_login_events},
         picture::upload_picture,
         root,
         session::{
             change_session_device_description, change_session_device_name,
             change_session_device_type, create_session, delete_session, get_some_sessions,
-            verify_session,
+            revoke_session, verify_session,
         },
+        suspend::{admin_account_suspension, admin_account_unsuspension},
     },
 };
+use clap::Parser;
 use dotenv::dotenv;
 use error::{DatabaseError, Error, SanitizeError, TokenError};
-use models::{Group, SessionToken};
+use models::{EmailTemplate, Permission, SessionToken};
 use regex::Regex;
 use routes::{change_info::admin_info_change, change_password::{begin_forgot_password, finish_forgot_password}};
 use sqlx::migrate;
+use std::str::FromStr;
 use tide::{
     http::headers::HeaderValue,
     security::{CorsMiddleware, Origin},
 };
 
+pub mod audit;
+pub mod bulk;
+pub mod cli;
+pub mod client_ip;
 pub mod config;
 pub mod database;
 pub mod email;
+pub mod email_transport;
 pub mod encryption;
 pub mod error;
+pub mod geolocation;
 pub mod models;
+pub mod notification;
+pub mod outbox;
 pub mod prelude;
+pub mod purge;
 pub mod random;
+pub mod request_log;
+pub mod resend;
 pub mod routes;
+pub mod templates;
 pub mod token;
+pub mod user_agent;
+pub mod verification;
 
 pub fn sanitize_handle(handle: &str) -> Result<String> {
     let handle_regex = Regex::new(r"^[a-zA-Z0-9_]+$").map_err(
@@ -59,25 +92,53 @@ pub fn sanitize_handle(handle: &str) -> Result<String> {
     }
 }
 
-pub async fn is_account_admin_from_id(id: &str) -> Result<bool> {
+pub async fn account_has_permission(id: &str, permission: Permission) -> Result<bool> {
     let query = sqlx::query!(
         r#"
-            SELECT "group"
-            FROM accounts
-            WHERE id = $1;
+            SELECT EXISTS(
+                SELECT 1
+                FROM accounts
+                JOIN role_permissions ON role_permissions.role = accounts."group"
+                WHERE accounts.id = $1 AND role_permissions.permission = $2
+            ) AS has_permission;
         "#,
-        id
+        id,
+        permission.to_string()
     );
 
     let result = match query.fetch_one(&*DATABASE_POOL).await {
         Ok(result) => result,
-        Err(sqlx::Error::RowNotFound) => return Err(Error::Database(DatabaseError::RowNotFound)),
         Err(error) => return Err(Error::Database(DatabaseError::FetchOne(error.to_string()))),
     };
 
-    let group = result.group;
+    Ok(result.has_permission.unwrap_or(false))
+}
+
+pub async fn get_account_permissions(id: &str) -> Result<Vec<Permission>> {
+    let query = sqlx::query!(
+        r#"
+            SELECT role_permissions.permission
+            FROM accounts
+            JOIN role_permissions ON role_permissions.role = accounts."group"
+            WHERE accounts.id = $1
+            ORDER BY role_permissions.permission;
+        "#,
+        id
+    );
+
+    let results = match query.fetch_all(&*DATABASE_POOL).await {
+        Ok(results) => results,
+        Err(error) => return Err(Error::Database(DatabaseError::FetchOne(error.to_string()))),
+    };
+
+    // IGNORE PERMISSIONS THIS VERSION OF THE SERVICE DOESN'T KNOW ABOUT
+
+    let permissions = results
+        .into_iter()
+        .filter_map(|result| Permission::from_str(&result.permission).ok())
+        .collect();
 
-    Ok(group == Group::Administrator.to_string())
+    Ok(permissions)
 }
 
 pub async fn get_id_from_handle(handle: &str) -> Result<String> {
@@ -169,15 +230,6 @@ pub async fn get_decode_verify_and_return_session_token(
     Ok(session_token)
 }
 
-pub fn string_to_email_placeholder(string: &str) -> String {
-    f!(
-        "{}{}{}",
-        CONFIG.email_placeholder_marker,
-        string,
-        CONFIG.email_placeholder_marker
-    )
-}
-
 #[async_std::main]
 async fn main() -> Result<()> {
     // Start logger
@@ -188,14 +240,37 @@ async fn main() -> Result<()> {
     log::info!("Loading environment variables...");
     dotenv().ok();
 
+    // Run a command instead of the server if one was given
+    let cli = cli::Cli::parse();
+
+    if let Some(command) = cli.command {
+        return cli::run_command(command).await;
+    }
+
+    // Load email templates
+    log::info!("Loading email templates...");
+    lazy_static::initialize(&templates::TEMPLATES);
+
+    // Set up the email transport
+    log::info!("Setting up {} email transport...", CONFIG.email_transport);
+    lazy_static::initialize(&email_transport::EMAIL_TRANSPORT);
+
+    // Load the DKIM key if signing is enabled
+    log::info!("Loading DKIM configuration...");
+    lazy_static::initialize(&email::DKIM_CONFIG);
+
+    // Check the verification code format of every flow
+    log::info!("Loading verification code formats...");
+    lazy_static::initialize(&verification::VERIFICATION_CODE_FORMATS);
+
     // Send test email if enabled
     if CONFIG.send_test_startup_email {
         log::info!("Sending test email...");
-        email::send_email(
+        email::send_templated_email(
             &CONFIG.manager_email_address,
-            &CONFIG.service_startup_email_subject,
-            &CONFIG.service_startup_email_body,
-            CONFIG.service_startup_email_html.to_owned(),
+            EmailTemplate::ServiceStartup,
+            None,
+            &tera::Context::new(),
         )?;
     } else {
         log::info!("Skipping test email...");
@@ -205,6 +280,14 @@ async fn main() -> Result<()> {
     log::info!("Running migrations...");
     migrate!("./migrations").run(&*DATABASE_POOL).await.unwrap();
 
+    // Purge accounts whose deletion grace period is over
+    log::info!("Starting account purge task...");
+    async_std::task::spawn(purge::run_account_purge());
+
+    // Deliver queued emails and build requested data exports in the background
+    log::info!("Starting email outbox task...");
+    async_std::task::spawn(outbox::run_email_outbox());
+
     let cors = CorsMiddleware::new()
         .allow_methods(
             "GET, POST, OPTIONS, DELETE, PATCH"
@@ -222,31 +305,55 @@ async fn main() -> Result<()> {
     // Create the server
     log::info!("Creating server...");
     let mut app = tide::new();
-    app.with(tide::log::LogMiddleware::new());
+    app.with(request_log::ClientAddressLogMiddleware);
     app.with(cors);
     app.at("/").get(root::root);
     app.at("/account").get(get_account);
     app.at("/forgot-password/begin").post(begin_forgot_password);
     app.at("/forgot-password/finish").post(finish_forgot_password);
+    app.at("/forgot-password/resend").post(resend_forgot_password);
     app.at("/admin").get(get_is_admin);
+    app.at("/admin/permissions").get(get_permissions);
+    app.at("/admin/audit-log").get(admin_get_audit_log);
+    app.at("/admin/audit-log/verify").get(admin_verify_audit_log);
+    app.at("/admin/email-outbox").get(admin_get_email_outbox);
+    app.at("/admin/email-outbox/retry").post(admin_retry_email_outbox_entry);
     app.at("/delete/begin").post(begin_account_deletion);
     app.at("/delete/finish").post(finish_account_deletion);
+    app.at("/delete/resend").post(resend_account_deletion);
+    app.at("/delete/cancel").post(cancel_account_deletion);
+    app.at("/data-export").post(request_data_export);
+    app.at("/data-export/download/:token").get(download_data_export);
     app.at("/change").patch(info_change);
     app.at("/admin/change").patch(admin_info_change);
     app.at("/admin/accounts").get(get_all_accounts);
+    app.at("/admin/accounts/import").post(admin_import_accounts);
+    app.at("/admin/accounts/export").get(admin_export_accounts);
     app.at("/admin/change/group").patch(admin_group_change);
     app.at("/admin/change/email").patch(admin_email_change);
     app.at("/admin/delete").patch(admin_account_deletion);
+    app.at("/admin/suspend").patch(admin_account_suspension);
+    app.at("/admin/unsuspend").patch(admin_account_unsuspension);
+    app.at("/admin/impersonate").post(admin_impersonation);
     app.at("/admin/change/password")
         .patch(admin_password_change);
     app.at("/change/email/begin").post(begin_email_change);
     app.at("/change/email/finish").post(finish_email_change);
+    app.at("/change/email/resend").post(resend_email_change);
+    app.at("/change/email/revert").post(revert_email_change);
     app.at("/change/password/begin").post(begin_password_change);
     app.at("/change/password/finish")
         .post(finish_password_change);
+    app.at("/change/password/resend").post(resend_password_change);
     app.at("/create/begin").post(begin_account_creation);
     app.at("/create/finish").post(finish_account_creation);
+    app.at("/create/resend").post(resend_account_creation);
+    app.at("/invites").post(create_invite);
+    app.at("/invites").get(get_invites);
     app.at("/sessions/:start/:ammount").get(get_some_sessions);
+    app.at("/login-events/:start/:ammount").get(get_login_events);
+    app.at("/admin/login-events/:account_id/:start/:ammount")
+        .get(admin_get_login_events);
     app.at("/session/device/type")
         .patch(change_session_device_type);
     app.at("/session/device/description")
@@ -257,6 +364,7 @@ async fn main() -> Result<()> {
     app.at("/session").delete(delete_session);
     app.at("/session/:session_id").delete(delete_session);
     app.at("/session/verify").get(verify_session);
+    app.at("/session/revoke").post(revoke_session);
     app.at("/picture").post(upload_picture);
 
     // Run the server
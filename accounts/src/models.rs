@@ -1,4 +1,4 @@
-use crate::config::CONFIG;
+use crate::{config::CONFIG, verification::verification_code_format};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
@@ -42,12 +42,71 @@ pub enum DeviceType {
     Other,
 }
 
+#[derive(Debug, Serialize, Deserialize, Display, EnumString)]
+pub enum Permission {
+    #[strum(serialize = "accounts.read")]
+    #[serde(rename = "accounts.read")]
+    AccountsRead,
+    #[strum(serialize = "accounts.edit")]
+    #[serde(rename = "accounts.edit")]
+    AccountsEdit,
+    #[strum(serialize = "accounts.change_email")]
+    #[serde(rename = "accounts.change_email")]
+    AccountsChangeEmail,
+    #[strum(serialize = "accounts.change_password")]
+    #[serde(rename = "accounts.change_password")]
+    AccountsChangePassword,
+    #[strum(serialize = "accounts.delete")]
+    #[serde(rename = "accounts.delete")]
+    AccountsDelete,
+    #[strum(serialize = "accounts.suspend")]
+    #[serde(rename = "accounts.suspend")]
+    AccountsSuspend,
+    #[strum(serialize = "accounts.impersonate")]
+    #[serde(rename = "accounts.impersonate")]
+    AccountsImpersonate,
+    #[strum(serialize = "accounts.import")]
+    #[serde(rename = "accounts.import")]
+    AccountsImport,
+    #[strum(serialize = "accounts.export")]
+    #[serde(rename = "accounts.export")]
+    AccountsExport,
+    #[strum(serialize = "sessions.read")]
+    #[serde(rename = "sessions.read")]
+    SessionsRead,
+    #[strum(serialize = "sessions.revoke")]
+    #[serde(rename = "sessions.revoke")]
+    SessionsRevoke,
+    #[strum(serialize = "groups.assign")]
+    #[serde(rename = "groups.assign")]
+    GroupsAssign,
+    #[strum(serialize = "audit_log.read")]
+    #[serde(rename = "audit_log.read")]
+    AuditLogRead,
+    #[strum(serialize = "invites.create")]
+    #[serde(rename = "invites.create")]
+    InvitesCreate,
+    #[strum(serialize = "email_outbox.manage")]
+    #[serde(rename = "email_outbox.manage")]
+    EmailOutboxManage,
+}
+
 #[derive(Debug, Serialize, Deserialize, Display, EnumString)]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
-pub enum Group {
-    Administrator,
-    Default,
+pub enum AuditAction {
+    GroupChange,
+    PasswordChange,
+    EmailChange,
+    InfoChange,
+    AccountDeletion,
+    AccountSuspension,
+    AccountUnsuspension,
+    ImpersonationStart,
+    ImpersonationEnd,
+    AccountImport,
+    AccountExport,
+    EmailChangeRevert,
 }
 
 #[derive(Debug, Serialize, Deserialize, Display, EnumString)]
@@ -59,6 +118,119 @@ pub enum Gender {
     NotSpecified,
 }
 
+#[derive(Debug, Serialize, Deserialize, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LoginEventOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LoginEventReason {
+    SessionCreated,
+    AccountNotFound,
+    WrongPassword,
+    AccountSuspended,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString, Clone, Copy)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountFileFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString, Clone, Copy)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTemplate {
+    ServiceStartup,
+    AccountCreationVerification,
+    EmailChangeOriginalEmailVerification,
+    EmailChangeNewEmailVerification,
+    PasswordChangeVerification,
+    ForgotPasswordVerification,
+    AccountDeletionVerification,
+    NewDeviceSignIn,
+    DataExportReady,
+    EmailChangeRevert,
+    PasswordChanged,
+    GroupChanged,
+    AccountDeletionScheduled,
+    AccountDeleted,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString, Clone, Copy)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTransportKind {
+    Smtp,
+    Sendmail,
+    File,
+    Memory,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString, Clone, Copy)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DkimAlgorithm {
+    Rsa,
+    Ed25519,
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Display, EnumString, Clone, Copy, PartialEq, Eq, Hash,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationFlow {
+    AccountCreation,
+    EmailChange,
+    PasswordChange,
+    ForgotPassword,
+    AccountDeletion,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DataExportStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EmailOutboxStatus {
+    Pending,
+    Sent,
+    Dead,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountSortColumn {
+    Handle,
+    Name,
+    Email,
+    CreatedAt,
+}
+
+#[derive(Debug, Serialize, Deserialize, Display, EnumString, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Account {
     pub id: String,
@@ -66,7 +238,7 @@ pub struct Account {
     pub name: String,
     pub email: String,
     pub password: String,
-    pub group: Group,
+    pub group: String,
     pub gender: Gender,
     pub email_is_public: bool,
     pub gender_is_public: bool,
@@ -77,6 +249,7 @@ pub struct Account {
     pub email_verification_codes_created_at: Option<NaiveDateTime>,
     pub new_password_verification_code: Option<String>,
     pub new_password_verification_code_created_at: Option<NaiveDateTime>,
+    pub preferred_language: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -85,12 +258,13 @@ pub struct AccountPublic {
     pub handle: Option<String>,
     pub name: Option<String>,
     pub email: Option<String>,
-    pub group: Option<Group>,
+    pub group: Option<String>,
     pub gender: Option<Gender>,
     pub email_is_public: Option<bool>,
     pub gender_is_public: Option<bool>,
     pub country_code: Option<String>,
     pub created_at: Option<NaiveDateTime>,
+    pub new_device_sign_in_email: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -101,6 +275,10 @@ pub struct Session {
     pub device_description: String,
     pub device_type: DeviceType,
     pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub country_code: Option<String>,
+    pub city: Option<String>,
+    pub impersonator_id: Option<String>,
     pub expire_date: NaiveDateTime,
     pub created_at: NaiveDateTime,
 }
@@ -116,6 +294,15 @@ pub struct SessionTokenInfo {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionToken {
     pub session: SessionTokenInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonator_id: Option<String>,
+    pub exp: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRevokeToken {
+    pub session_id: String,
+    pub account_id: String,
     pub exp: usize,
 }
 
@@ -134,6 +321,35 @@ pub struct SessionList {
     pub sessions: Vec<Session>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginEvent {
+    pub id: String,
+    pub account_id: Option<String>,
+    pub email: String,
+    pub session_id: Option<String>,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub device_name: String,
+    pub device_description: String,
+    pub device_type: DeviceType,
+    pub outcome: LoginEventOutcome,
+    pub reason: LoginEventReason,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginEventList {
+    pub login_events: Vec<LoginEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct GetLoginEventsRequest {
+    #[validate(range(min = 0))]
+    pub start: i64,
+    #[validate(range(min = 1, max = 1000))]
+    pub ammount: i64,
+}
+
 // Region: Account Creation
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -142,6 +358,7 @@ pub struct AccountCreationVerification {
     pub handle: String,
     pub verification_code: String,
     pub verification_code_created_at: NaiveDateTime,
+    pub invite_code: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -150,11 +367,15 @@ pub struct BeginAccountCreationRequest {
     pub handle: String,
     #[validate(email)]
     pub email: String,
+    #[validate(length(min = 1))]
+    pub invite_code: Option<String>,
+    #[validate(length(min = 1))]
+    pub preferred_language: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct FinishAccountCreationRequest {
-    #[validate(custom = "validate_verification_code_length")]
+    #[validate(custom = "validate_account_creation_code")]
     pub verification_code: String,
     #[validate(length(min = 1), custom = "validate_handle_length")]
     pub handle: String,
@@ -169,10 +390,34 @@ pub struct FinishAccountCreationRequest {
     pub gender_is_public: bool,
     #[validate(length(min = 1))]
     pub country_code: String,
+    #[validate(length(min = 1))]
+    pub preferred_language: Option<String>,
 }
 
 // End region: Create Account Request Model
 
+// Region: Verification Resend Models
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResendAccountCreationRequest {
+    #[validate(email)]
+    pub email: String,
+    pub preferred_language: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResendForgotPasswordRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResendVerificationResponse {
+    pub cooldown_seconds: i64,
+}
+
+// End region: Verification Resend Models
+
 // Region: Sessions Request Models
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -182,7 +427,7 @@ pub struct CreateSessionRequest {
     #[validate(length(min = 1))]
     pub password: String,
     #[validate(length(min = 1), custom = "validate_device_name_max_length")]
-    pub device_name: String,
+    pub device_name: Option<String>,
     #[validate(length(min = 1), custom = "validate_device_description_max_length")]
     pub device_description: String,
 }
@@ -216,6 +461,12 @@ pub struct ChangeSessionDeviceDescriptionRequest {
     pub device_description: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RevokeSessionRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct GetSessionsRequest {
     #[validate(custom = "validate_session_id_length")]
@@ -245,12 +496,18 @@ pub struct BeginEmailChangeRequest {
 pub struct FinishEmailChangeRequest {
     #[validate(email)]
     pub email: String,
-    #[validate(custom = "validate_verification_code_length")]
+    #[validate(custom = "validate_email_change_code")]
     pub original_email_verification_code: String,
-    #[validate(custom = "validate_verification_code_length")]
+    #[validate(custom = "validate_email_change_code")]
     pub new_email_verification_code: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RevertEmailChangeRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+}
+
 // End region: Email Change Request Model
 
 // Region: Group Change Request Model
@@ -259,7 +516,8 @@ pub struct FinishEmailChangeRequest {
 pub struct AdminGroupChangeRequest {
     #[validate(length(min = 1), custom = "validate_account_id_length")]
     pub account_id: String,
-    pub group: Group,
+    #[validate(length(min = 1))]
+    pub group: String,
 }
 
 // End region: Group Change Request Model
@@ -284,6 +542,7 @@ pub struct BeginForgotPasswordRequest {
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct FinishForgotPasswordRequest {
     pub email: String,
+    #[validate(custom = "validate_forgot_password_code")]
     pub verification_code: String,
     pub new_password: String,
 }
@@ -292,7 +551,7 @@ pub struct FinishForgotPasswordRequest {
 pub struct FinishPasswordChangeRequest {
     #[validate(length(min = 1))]
     pub password: String,
-    #[validate(custom = "validate_verification_code_length")]
+    #[validate(custom = "validate_password_change_code")]
     pub verification_code: String,
 }
 
@@ -308,12 +567,187 @@ pub struct AdminAccountDeletionRequest {
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct FinishAccountDeletionRequest {
-    #[validate(custom = "validate_verification_code_length")]
+    #[validate(custom = "validate_account_deletion_code")]
     pub verification_code: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingAccountDeletion {
+    pub deletion_scheduled_for: NaiveDateTime,
+}
+
 // End region: Account Deletion Request Model
 
+// Region: Account Suspension Models
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AdminAccountSuspensionRequest {
+    #[validate(length(min = 1), custom = "validate_account_id_length")]
+    pub account_id: String,
+    #[validate(length(min = 1, max = 1000))]
+    pub reason: String,
+    pub until: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AdminAccountUnsuspensionRequest {
+    #[validate(length(min = 1), custom = "validate_account_id_length")]
+    pub account_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountSuspended {
+    pub reason: String,
+    pub suspended_until: Option<NaiveDateTime>,
+}
+
+// End region: Account Suspension Models
+
+// Region: Impersonation Request Model
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AdminImpersonationRequest {
+    #[validate(length(min = 1), custom = "validate_account_id_length")]
+    pub account_id: String,
+}
+
+// End region: Impersonation Request Model
+
+// Region: Invite Models
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateInviteRequest {
+    #[validate(range(min = 1))]
+    pub max_uses: Option<i32>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Invite {
+    pub code: String,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub redemptions: Vec<InviteRedemption>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteRedemption {
+    pub account_id: String,
+    pub handle: String,
+    pub redeemed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetInvitesResponse {
+    pub invites: Vec<Invite>,
+    pub remaining_quota: Option<i64>,
+}
+
+// End region: Invite Models
+
+// Region: Data Export Models
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataExport {
+    pub id: String,
+    pub status: DataExportStatus,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataExportDownloadToken {
+    pub export_id: String,
+    pub account_id: String,
+    pub exp: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataExportAccount {
+    pub id: String,
+    pub handle: String,
+    pub name: String,
+    pub email: String,
+    pub group: String,
+    pub gender: String,
+    pub country_code: String,
+    pub email_is_public: bool,
+    pub gender_is_public: bool,
+    pub new_device_sign_in_email: bool,
+    pub preferred_language: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+// End region: Data Export Models
+
+// Region: Account Import And Export Models
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ImportedAccount {
+    #[validate(length(min = 1), custom = "validate_handle_length")]
+    pub handle: String,
+    #[validate(length(min = 1), custom = "validate_name_length")]
+    pub name: String,
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(min = 1))]
+    pub password: String,
+    pub group: Option<String>,
+    pub gender: Option<Gender>,
+    pub email_is_public: Option<bool>,
+    pub gender_is_public: Option<bool>,
+    #[validate(length(min = 1))]
+    pub country_code: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedAccount {
+    pub id: String,
+    pub handle: String,
+    pub name: String,
+    pub email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub group: String,
+    pub gender: Gender,
+    pub email_is_public: bool,
+    pub gender_is_public: bool,
+    pub country_code: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub total: usize,
+    pub valid: usize,
+    pub imported: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountImportRequest {
+    pub format: AccountFileFormat,
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountExportRequest {
+    pub format: AccountFileFormat,
+    pub include_password_hashes: Option<bool>,
+}
+
+// End region: Account Import And Export Models
+
 // Region: Account Info Change Request Models
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -325,6 +759,9 @@ pub struct AccountInfoChangeRequest {
     pub gender: Option<Gender>,
     #[validate(length(min = 1))]
     pub country_code: Option<String>,
+    pub new_device_sign_in_email: Option<bool>,
+    #[validate(length(min = 1))]
+    pub preferred_language: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -336,6 +773,85 @@ pub struct AdminAccountInfoChangeRequest {
 
 // End region: Account Info Change Request Models
 
+// Region: Audit Log Request Models
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub actor_id: String,
+    pub target_id: String,
+    pub action: String,
+    pub diff: serde_json::Value,
+    pub ip_address: String,
+    pub created_at: NaiveDateTime,
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditLogList {
+    pub entries: Vec<AuditLogEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditLogVerification {
+    pub valid: bool,
+    pub checked_entries: i64,
+    pub first_invalid_entry_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct GetAuditLogRequest {
+    pub actor_id: Option<String>,
+    pub target_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    #[validate(range(min = 0))]
+    pub start: i64,
+    #[validate(range(min = 1, max = 1000))]
+    pub ammount: i64,
+}
+
+// End region: Audit Log Request Models
+
+// Region: Email Outbox Models
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailOutboxEntry {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailOutboxList {
+    pub entries: Vec<EmailOutboxEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct GetEmailOutboxRequest {
+    pub status: Option<EmailOutboxStatus>,
+    pub recipient: Option<String>,
+    #[validate(range(min = 0))]
+    pub start: i64,
+    #[validate(range(min = 1, max = 1000))]
+    pub ammount: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetryEmailOutboxEntryRequest {
+    pub id: i64,
+}
+
+// End region: Email Outbox Models
+
 // Region: Account Get Request Models
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -344,7 +860,7 @@ pub struct GetAllAccountsAccount {
     pub handle: String,
     pub name: String,
     pub email: String,
-    pub group: Group,
+    pub group: String,
     pub gender: Gender,
     pub email_is_public: bool,
     pub gender_is_public: bool,
@@ -352,11 +868,38 @@ pub struct GetAllAccountsAccount {
     pub created_at: NaiveDateTime,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PermissionList {
+    pub permissions: Vec<Permission>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetAllAccountsResponse {
     pub accounts: Vec<GetAllAccountsAccount>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct GetAccountsPageRequest {
+    #[validate(length(min = 1, max = 256))]
+    pub search: Option<String>,
+    pub group: Option<String>,
+    pub country_code: Option<String>,
+    pub created_from: Option<NaiveDateTime>,
+    pub created_to: Option<NaiveDateTime>,
+    pub sort: Option<AccountSortColumn>,
+    pub order: Option<SortOrder>,
+    pub cursor: Option<String>,
+    #[validate(range(min = 1, max = 1000))]
+    pub ammount: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetAccountsPageResponse {
+    pub accounts: Vec<GetAllAccountsAccount>,
+    pub total_count: i64,
+    pub next_cursor: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccountInfoToGet {
     pub id: Option<bool>,
@@ -369,6 +912,7 @@ pub struct AccountInfoToGet {
     pub gender_is_public: Option<bool>,
     pub country_code: Option<bool>,
     pub created_at: Option<bool>,
+    pub new_device_sign_in_email: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -406,14 +950,40 @@ fn validate_device_description_max_length(device_description: &str) -> Result<()
     Ok(())
 }
 
-fn validate_verification_code_length(handle: &str) -> Result<(), ValidationError> {
-    if handle.len() != CONFIG.verification_code_length {
+fn validate_verification_code(flow: VerificationFlow, code: &str) -> Result<(), ValidationError> {
+    let format = verification_code_format(flow);
+
+    if code.chars().count() != format.length {
         return Err(ValidationError::new("verification_code_length_wrong"));
     }
 
+    if !code.chars().all(|character| format.alphabet.contains(&character)) {
+        return Err(ValidationError::new("verification_code_character_invalid"));
+    }
+
     Ok(())
 }
 
+fn validate_account_creation_code(code: &str) -> Result<(), ValidationError> {
+    validate_verification_code(VerificationFlow::AccountCreation, code)
+}
+
+fn validate_email_change_code(code: &str) -> Result<(), ValidationError> {
+    validate_verification_code(VerificationFlow::EmailChange, code)
+}
+
+fn validate_password_change_code(code: &str) -> Result<(), ValidationError> {
+    validate_verification_code(VerificationFlow::PasswordChange, code)
+}
+
+fn validate_forgot_password_code(code: &str) -> Result<(), ValidationError> {
+    validate_verification_code(VerificationFlow::ForgotPassword, code)
+}
+
+fn validate_account_deletion_code(code: &str) -> Result<(), ValidationError> {
+    validate_verification_code(VerificationFlow::AccountDeletion, code)
+}
+
 fn validate_handle_length(handle: &str) -> Result<(), ValidationError> {
     if handle.len() > CONFIG.handle_max_length {
         return Err(ValidationError::new("handle_length_exceeded"));
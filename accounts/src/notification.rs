@@ -0,0 +1,72 @@
+use crate::{
+    config::CONFIG,
+    email::queue_templated_email,
+    error::{DatabaseError, Error},
+    models::EmailTemplate,
+    prelude::*,
+};
+use chrono::Utc;
+use sqlx::PgConnection;
+use tera::Context;
+
+// Security notifications tell the owner of an account about a sensitive change
+// made to it, every kind of them can be switched off in the config
+
+fn security_notification_enabled(template: EmailTemplate) -> bool {
+    match template {
+        EmailTemplate::PasswordChanged => CONFIG.password_change_notification_email,
+        EmailTemplate::GroupChanged => CONFIG.group_change_notification_email,
+        EmailTemplate::AccountDeletionScheduled | EmailTemplate::AccountDeleted => {
+            CONFIG.account_deletion_notification_email
+        }
+        _ => true,
+    }
+}
+
+// Must be called before the account is deleted, while its address can still be read
+pub async fn queue_security_notification(
+    connection: &mut PgConnection,
+    account_id: &str,
+    template: EmailTemplate,
+    mut context: Context,
+) -> Result<()> {
+    if !security_notification_enabled(template) {
+        return Ok(());
+    }
+
+    // GET THE OWNER'S ADDRESS AND LANGUAGE
+
+    let query = sqlx::query!(
+        r#"
+            SELECT handle, email, preferred_language
+            FROM accounts
+            WHERE id = $1
+        "#,
+        account_id
+    );
+
+    let account = query
+        .fetch_one(&mut *connection)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    // FILL THE DETAILS SHARED BY EVERY NOTIFICATION
+
+    context.insert("handle", &account.handle);
+    context.insert(
+        "time",
+        &Utc::now()
+            .naive_utc()
+            .format("%Y-%m-%d %H:%M:%S UTC")
+            .to_string(),
+    );
+
+    queue_templated_email(
+        connection,
+        &account.email,
+        template,
+        account.preferred_language.as_deref(),
+        &context,
+    )
+    .await
+}
@@ -0,0 +1,186 @@
+use crate::{
+    config::CONFIG,
+    database::DATABASE_POOL,
+    email::send_email,
+    error::{DatabaseError, Error},
+    models::EmailOutboxStatus,
+    prelude::*,
+    routes::data_export::build_pending_data_exports,
+};
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+
+// A claimed email is left alone by other workers for this long,
+// if the worker dies while sending it the email is picked up again afterwards
+const EMAIL_OUTBOX_LEASE_SECONDS: i64 = 300;
+
+// Keeps the backoff from overflowing, it's already days long by then
+const EMAIL_OUTBOX_MAX_BACKOFF_EXPONENT: u32 = 16;
+
+struct ClaimedEmail {
+    id: i64,
+    recipient: String,
+    subject: String,
+    text_body: String,
+    html_body: Option<String>,
+    attempts: i32,
+}
+
+async fn claim_due_emails() -> Result<Vec<ClaimedEmail>> {
+    let now = Utc::now().naive_utc();
+
+    // PUSH THE NEXT ATTEMPT OF THE CLAIMED ROWS PAST THE LEASE,
+    // SKIP LOCKED LETS SEVERAL WORKERS CLAIM DIFFERENT ROWS AT ONCE
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE email_outbox
+            SET next_attempt_at = $1
+            WHERE id IN (
+                SELECT id
+                FROM email_outbox
+                WHERE status = $2 AND next_attempt_at <= $3
+                ORDER BY next_attempt_at
+                LIMIT $4
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, recipient, subject, text_body, html_body, attempts
+        "#,
+        now + ChronoDuration::seconds(EMAIL_OUTBOX_LEASE_SECONDS),
+        EmailOutboxStatus::Pending.to_string(),
+        now,
+        CONFIG.email_outbox_batch_size
+    );
+
+    let emails = query
+        .fetch_all(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .into_iter()
+        .map(|email| ClaimedEmail {
+            id: email.id,
+            recipient: email.recipient,
+            subject: email.subject,
+            text_body: email.text_body,
+            html_body: email.html_body,
+            attempts: email.attempts,
+        })
+        .collect();
+
+    Ok(emails)
+}
+
+async fn mark_email_sent(id: i64) -> Result<()> {
+    let query = sqlx::query!(
+        r#"
+            UPDATE email_outbox
+            SET status = $1, attempts = attempts + 1, last_error = NULL, sent_at = $2
+            WHERE id = $3
+        "#,
+        EmailOutboxStatus::Sent.to_string(),
+        Utc::now().naive_utc(),
+        id
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+async fn mark_email_failed(email: &ClaimedEmail, error: &str) -> Result<()> {
+    let attempts = email.attempts + 1;
+
+    // RETRY AFTER BASE * 2^(ATTEMPTS - 1) SECONDS UNTIL THE ATTEMPTS RUN OUT
+
+    let status = if attempts >= CONFIG.email_outbox_max_attempts {
+        EmailOutboxStatus::Dead
+    } else {
+        EmailOutboxStatus::Pending
+    };
+
+    let exponent = ((attempts - 1).max(0) as u32).min(EMAIL_OUTBOX_MAX_BACKOFF_EXPONENT);
+    let backoff = CONFIG.email_outbox_retry_base_seconds * 2_i64.pow(exponent);
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE email_outbox
+            SET status = $1, attempts = $2, last_error = $3, next_attempt_at = $4
+            WHERE id = $5
+        "#,
+        status.to_string(),
+        attempts,
+        error,
+        Utc::now().naive_utc() + ChronoDuration::seconds(backoff),
+        email.id
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    match status {
+        EmailOutboxStatus::Dead => log::error!(
+            "Gave up on email {} to {} after {} attempts: {}",
+            email.id,
+            email.recipient,
+            attempts,
+            error
+        ),
+        _ => log::warn!(
+            "Failed to send email {} to {}, retrying in {} seconds: {}",
+            email.id,
+            email.recipient,
+            backoff,
+            error
+        ),
+    }
+
+    Ok(())
+}
+
+pub async fn deliver_due_emails() -> Result<()> {
+    for email in claim_due_emails().await? {
+        // SMTP IS BLOCKING, KEEP IT OFF THE ASYNC EXECUTOR
+
+        let (recipient, subject, text_body, html_body) = (
+            email.recipient.to_owned(),
+            email.subject.to_owned(),
+            email.text_body.to_owned(),
+            email.html_body.to_owned(),
+        );
+
+        let result = async_std::task::spawn_blocking(move || {
+            send_email(&recipient, &subject, &text_body, html_body.as_deref())
+        })
+        .await;
+
+        match result {
+            Ok(_) => mark_email_sent(email.id).await?,
+            Err(err) => mark_email_failed(&email, &f!("{:?}", err)).await?,
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_email_outbox() {
+    let interval = Duration::from_secs(CONFIG.email_outbox_poll_interval_seconds);
+
+    loop {
+        if let Err(err) = deliver_due_emails().await {
+            log::error!("Failed to deliver queued emails: {}", err);
+        }
+
+        // DATA EXPORTS ARE BUILT BY THE SAME WORKER SO THEY SURVIVE A RESTART
+
+        if let Err(err) = build_pending_data_exports().await {
+            log::error!("Failed to build pending data exports: {}", err);
+        }
+
+        async_std::task::sleep(interval).await;
+    }
+}
@@ -0,0 +1,238 @@
+use crate::{
+    config::CONFIG,
+    database::DATABASE_POOL,
+    error::{DatabaseError, Error},
+    models::{EmailOutboxStatus, EmailTemplate, VerificationFlow},
+    notification::queue_security_notification,
+    prelude::*,
+    routes::picture::delete_picture_from_bucket,
+    verification::verification_code_format,
+};
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+use tera::Context;
+
+async fn purge_account(account_id: &str) -> Result<()> {
+    let mut transaction = DATABASE_POOL
+        .begin()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    // LOCK THE ACCOUNT AND CHECK IT'S STILL DUE,
+    // THE OWNER MIGHT HAVE CANCELLED IN THE MEANTIME
+
+    let query = sqlx::query!(
+        r#"
+            SELECT id
+            FROM accounts
+            WHERE id = $1 AND deletion_scheduled_for <= $2
+            FOR UPDATE SKIP LOCKED
+        "#,
+        account_id,
+        Utc::now().naive_utc()
+    );
+
+    let is_due = query
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .is_some();
+
+    if !is_due {
+        return Ok(());
+    }
+
+    // REMOVE THE PICTURE FIRST, IF THAT FAILS THE ACCOUNT
+    // STAYS AROUND AND IS RETRIED ON THE NEXT RUN
+
+    delete_picture_from_bucket(&f!("{}.webp", account_id)).await?;
+
+    // LET THE OWNER KNOW WHILE THEIR ADDRESS IS STILL AROUND
+
+    queue_security_notification(
+        &mut transaction,
+        account_id,
+        EmailTemplate::AccountDeleted,
+        Context::new(),
+    )
+    .await?;
+
+    // DELETE THE ACCOUNT, CASCADING TO ITS SESSIONS
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM accounts
+            WHERE id = $1
+        "#,
+        account_id
+    );
+
+    query
+        .execute(&mut *transaction)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+pub async fn purge_accounts_pending_deletion() -> Result<()> {
+    // GET EVERY ACCOUNT WHOSE GRACE PERIOD IS OVER
+
+    let query = sqlx::query!(
+        r#"
+            SELECT id
+            FROM accounts
+            WHERE deletion_scheduled_for <= $1
+        "#,
+        Utc::now().naive_utc()
+    );
+
+    let accounts = query
+        .fetch_all(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    // PURGE THEM ONE BY ONE SO A FAILURE DOESN'T HOLD BACK THE OTHERS
+
+    for account in accounts {
+        match purge_account(&account.id).await {
+            Ok(_) => log::info!("Purged account {}", account.id),
+            Err(err) => log::error!("Failed to purge account {}: {}", account.id, err),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn purge_expired_data_exports() -> Result<()> {
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM data_exports
+            WHERE expires_at <= $1
+        "#,
+        Utc::now().naive_utc()
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+pub async fn purge_sent_emails() -> Result<()> {
+    // DEAD EMAILS ARE KEPT SO AN ADMINISTRATOR CAN LOOK INTO THEM
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM email_outbox
+            WHERE status = $1 AND sent_at <= $2
+        "#,
+        EmailOutboxStatus::Sent.to_string(),
+        Utc::now().naive_utc() - ChronoDuration::days(CONFIG.email_outbox_retention_days)
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+pub async fn purge_expired_email_change_reverts() -> Result<()> {
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM email_change_reverts
+            WHERE expires_at <= $1
+        "#,
+        Utc::now().naive_utc()
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+pub async fn purge_expired_account_creation_verifications() -> Result<()> {
+    // AN EXPIRED SIGNUP CAN'T BE FINISHED, DROPPING IT RELEASES ITS HANDLE
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM account_creation_verifications
+            WHERE verification_code_created_at <= $1
+        "#,
+        Utc::now().naive_utc() - verification_code_format(VerificationFlow::AccountCreation).ttl
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+pub async fn purge_expired_verification_resends() -> Result<()> {
+    // ONCE THE COOLDOWN IS OVER THE ROW NO LONGER HOLDS ANYTHING BACK
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM verification_resends
+            WHERE sent_at <= $1
+        "#,
+        Utc::now().naive_utc()
+            - ChronoDuration::seconds(CONFIG.verification_resend_cooldown_seconds)
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+pub async fn run_account_purge() {
+    let interval = Duration::from_secs(CONFIG.account_purge_interval_seconds);
+
+    loop {
+        if let Err(err) = purge_accounts_pending_deletion().await {
+            log::error!("Failed to purge accounts pending deletion: {}", err);
+        }
+
+        if let Err(err) = purge_expired_data_exports().await {
+            log::error!("Failed to purge expired data exports: {}", err);
+        }
+
+        if let Err(err) = purge_expired_email_change_reverts().await {
+            log::error!("Failed to purge expired email change reverts: {}", err);
+        }
+
+        if let Err(err) = purge_expired_account_creation_verifications().await {
+            log::error!(
+                "Failed to purge expired account creation verifications: {}",
+                err
+            );
+        }
+
+        if let Err(err) = purge_expired_verification_resends().await {
+            log::error!("Failed to purge expired verification resends: {}", err);
+        }
+
+        if let Err(err) = purge_sent_emails().await {
+            log::error!("Failed to purge sent emails: {}", err);
+        }
+
+        async_std::task::sleep(interval).await;
+    }
+}
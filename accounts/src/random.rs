@@ -1,24 +1,63 @@
-use rand::{thread_rng, Rng};
+use rand::{rngs::OsRng, Rng};
+
+const ALPHANUMERIC_CHARACTERS: &str =
+    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
 
 pub fn get_random_string(length: usize) -> String {
-    let characters: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
-
-    let mut rng = thread_rng();
-    let random_string: String = (0..length)
-        .map(|_| {
-            let idx = rng.gen_range(0..characters.len());
-            characters[idx] as char
-        })
-        .collect();
+    let characters: Vec<char> = ALPHANUMERIC_CHARACTERS.chars().collect();
 
-    random_string
+    get_random_code(&characters, length)
 }
 
-pub fn get_random_numbers(length: usize) -> String {
-    let mut rng = thread_rng();
-    let random_numbers: String = (0..length)
-        .map(|_| rng.gen_range(0..9).to_string())
+// Draws from the operating system's CSPRNG, gen_range rejects the samples
+// that would bias the modulo so every character is equally likely
+pub fn get_random_code(alphabet: &[char], length: usize) -> String {
+    let random_code: String = (0..length)
+        .map(|_| alphabet[OsRng.gen_range(0..alphabet.len())])
         .collect();
 
-    random_numbers
+    random_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGITS: [char; 10] = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
+
+    #[test]
+    fn random_code_only_uses_the_alphabet() {
+        let alphabet = ['A', 'C', 'E', '7'];
+        let code = get_random_code(&alphabet, 64);
+
+        assert_eq!(code.chars().count(), 64);
+        assert!(code.chars().all(|character| alphabet.contains(&character)));
+    }
+
+    #[test]
+    fn random_digits_are_uniformly_distributed() {
+        const SAMPLES: usize = 100_000;
+
+        let code = get_random_code(&DIGITS, SAMPLES);
+
+        let mut counts = [0_usize; 10];
+        for digit in code.chars() {
+            counts[digit.to_digit(10).unwrap() as usize] += 1;
+        }
+
+        // EVERY DIGIT MUST SHOW UP, INCLUDING 9
+
+        assert!(counts.iter().all(|count| *count > 0), "{:?}", counts);
+
+        // CHI-SQUARED WITH 9 DEGREES OF FREEDOM, A UNIFORM SOURCE
+        // GOES OVER 40 ONCE EVERY FEW HUNDRED THOUSAND RUNS
+
+        let expected = SAMPLES as f64 / 10.0;
+        let chi_squared: f64 = counts
+            .iter()
+            .map(|count| (*count as f64 - expected).powi(2) / expected)
+            .sum();
+
+        assert!(chi_squared < 40.0, "{:?} {}", counts, chi_squared);
+    }
 }
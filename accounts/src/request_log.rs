@@ -0,0 +1,22 @@
+use crate::client_ip::get_client_ip_address;
+use tide::{log, utils::async_trait, Middleware, Next, Request};
+
+// Tide always logs the requests it receives and the responses it sends, but
+// it has no idea of our proxies, so every request also gets logged with the
+// client address worked out from the trusted proxies
+
+#[derive(Debug, Default, Clone)]
+pub struct ClientAddressLogMiddleware;
+
+#[async_trait]
+impl Middleware<()> for ClientAddressLogMiddleware {
+    async fn handle(&self, req: Request<()>, next: Next<'_, ()>) -> tide::Result {
+        log::info!("<-- Request client", {
+            method: req.method().to_string(),
+            path: req.url().path(),
+            client_ip: get_client_ip_address(&req),
+        });
+
+        Ok(next.run(req).await)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{
+    config::CONFIG,
+    error::{DatabaseError, Error},
+    models::VerificationFlow,
+    prelude::*,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use sqlx::PgConnection;
+
+// Returns the seconds left before the pending challenge of the target can be
+// resent, or None after recording this resend so the cooldown starts over.
+// Callers lock the challenge row first so concurrent resends can't both pass
+pub async fn start_resend_cooldown(
+    connection: &mut PgConnection,
+    flow: VerificationFlow,
+    target: &str,
+    challenge_created_at: NaiveDateTime,
+) -> Result<Option<i64>> {
+    let now = Utc::now().naive_utc();
+
+    // THE COOLDOWN RUNS FROM THE LAST TIME THE CODE WAS SENT,
+    // EITHER BY THE BEGIN ENDPOINT OR BY A PREVIOUS RESEND
+
+    let query = sqlx::query!(
+        r#"
+            SELECT sent_at
+            FROM verification_resends
+            WHERE flow = $1 AND target = $2
+        "#,
+        flow.to_string(),
+        target
+    );
+
+    let last_sent_at = query
+        .fetch_optional(&mut *connection)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .map(|resend| resend.sent_at.max(challenge_created_at))
+        .unwrap_or(challenge_created_at);
+
+    let remaining = (last_sent_at + Duration::seconds(CONFIG.verification_resend_cooldown_seconds)
+        - now)
+        .num_seconds();
+
+    if remaining > 0 {
+        return Ok(Some(remaining));
+    }
+
+    // RECORD THIS RESEND
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO verification_resends (flow, target, sent_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (flow, target) DO UPDATE
+            SET sent_at = EXCLUDED.sent_at
+        "#,
+        flow.to_string(),
+        target,
+        now
+    );
+
+    query
+        .execute(&mut *connection)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(None)
+}
@@ -0,0 +1,179 @@
+use crate::{
+    account_has_permission,
+    audit::{hash_audit_log_entry, AUDIT_LOG_GENESIS_HASH},
+    database::DATABASE_POOL,
+    get_decode_verify_and_return_session_token,
+    models::{AuditLogEntry, AuditLogList, AuditLogVerification, GetAuditLogRequest, Permission},
+};
+use tide::{convert::json, Response, StatusCode};
+use validator::Validate;
+
+pub async fn admin_get_audit_log(req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST INFO FROM QUERY PARAMS AND VALIDATE IT
+
+    let info: GetAuditLogRequest = req.query()?;
+
+    if info.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(info.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO READ THE AUDIT LOG
+
+    match account_has_permission(&account_id, Permission::AuditLogRead).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // GET THE ENTRIES MATCHING THE FILTERS, NEWEST FIRST
+
+    let query = sqlx::query!(
+        r#"
+            SELECT *
+            FROM audit_log
+            WHERE ($1::TEXT IS NULL OR actor_id = $1)
+                AND ($2::TEXT IS NULL OR target_id = $2)
+                AND ($3::TEXT IS NULL OR action = $3)
+                AND ($4::TIMESTAMP IS NULL OR created_at >= $4)
+                AND ($5::TIMESTAMP IS NULL OR created_at < $5)
+            ORDER BY id DESC
+            OFFSET $6
+            LIMIT $7
+        "#,
+        info.actor_id,
+        info.target_id,
+        info.action.map(|action| action.to_string()),
+        info.from,
+        info.to,
+        info.start,
+        info.ammount
+    );
+
+    let entries = query
+        .fetch_all(&*DATABASE_POOL)
+        .await?
+        .into_iter()
+        .map(|entry| AuditLogEntry {
+            id: entry.id,
+            actor_id: entry.actor_id,
+            target_id: entry.target_id,
+            action: entry.action,
+            diff: entry.diff,
+            ip_address: entry.ip_address,
+            created_at: entry.created_at,
+            previous_hash: entry.previous_hash,
+            hash: entry.hash,
+        })
+        .collect();
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(AuditLogList { entries }))
+        .build();
+
+    Ok(response)
+}
+
+pub async fn admin_verify_audit_log(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO READ THE AUDIT LOG
+
+    match account_has_permission(&account_id, Permission::AuditLogRead).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // WALK THE WHOLE CHAIN FROM THE FIRST ENTRY AND RECOMPUTE EVERY HASH
+
+    let query = sqlx::query!(
+        r#"
+            SELECT *
+            FROM audit_log
+            ORDER BY id
+        "#
+    );
+
+    let entries = query.fetch_all(&*DATABASE_POOL).await?;
+
+    let mut previous_hash = AUDIT_LOG_GENESIS_HASH.to_string();
+    let mut checked_entries = 0;
+    let mut first_invalid_entry_id = None;
+
+    for entry in entries {
+        let hash = hash_audit_log_entry(
+            &previous_hash,
+            &entry.actor_id,
+            &entry.target_id,
+            &entry.action,
+            &entry.diff,
+            &entry.ip_address,
+            &entry.created_at,
+        );
+
+        checked_entries += 1;
+
+        if entry.previous_hash != previous_hash || entry.hash != hash {
+            first_invalid_entry_id = Some(entry.id);
+            break;
+        }
+
+        previous_hash = entry.hash;
+    }
+
+    // SEND RESPONSE
+
+    let verification = AuditLogVerification {
+        valid: first_invalid_entry_id.is_none(),
+        checked_entries,
+        first_invalid_entry_id,
+    };
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(verification))
+        .build();
+
+    Ok(response)
+}
@@ -0,0 +1,128 @@
+use std::str::FromStr;
+use tide::{convert::json, http::mime, Response, StatusCode};
+
+use crate::{
+    account_has_permission,
+    bulk::{export_accounts, import_accounts},
+    client_ip::get_client_ip_address,
+    get_decode_verify_and_return_session_token,
+    models::{AccountExportRequest, AccountFileFormat, AccountImportRequest, Permission},
+};
+
+pub async fn admin_import_accounts(mut req: tide::Request<()>) -> tide::Result {
+    // GET IMPORT OPTIONS FROM THE QUERY AND THE FILE FROM THE BODY
+
+    let info: AccountImportRequest = req.query()?;
+    let data = req.body_string().await?;
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO IMPORT ACCOUNTS
+
+    match account_has_permission(&account_id, Permission::AccountsImport).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // IMPORT THE ACCOUNTS, NOTHING IS WRITTEN IF ANY RECORD IS INVALID,
+    // THEY CAN ONLY BE PUT IN GROUPS WITHIN THE USER'S OWN PERMISSIONS
+
+    let report = import_accounts(
+        info.format,
+        &data,
+        info.dry_run.unwrap_or(false),
+        Some(&account_id),
+        &account_id,
+        &get_client_ip_address(&req),
+    )
+    .await?;
+
+    // SEND RESPONSE
+
+    let status = if report.dry_run || report.errors.is_empty() {
+        StatusCode::Ok
+    } else {
+        StatusCode::UnprocessableEntity
+    };
+
+    let response = Response::builder(status).body(json!(report)).build();
+
+    Ok(response)
+}
+
+pub async fn admin_export_accounts(req: tide::Request<()>) -> tide::Result {
+    // GET EXPORT OPTIONS FROM THE QUERY
+
+    let info: AccountExportRequest = req.query()?;
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO EXPORT ACCOUNTS
+
+    match account_has_permission(&account_id, Permission::AccountsExport).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // EXPORT EVERY ACCOUNT, PASSWORD HASHES ONLY IF ASKED FOR
+
+    let data = export_accounts(
+        info.format,
+        info.include_password_hashes.unwrap_or(false),
+        &account_id,
+        &get_client_ip_address(&req),
+    )
+    .await?;
+
+    // SEND RESPONSE
+
+    let content_type = match info.format {
+        AccountFileFormat::Csv => "text/csv",
+        AccountFileFormat::Jsonl => "application/x-ndjson",
+    };
+
+    let response = Response::builder(StatusCode::Ok)
+        .content_type(mime::Mime::from_str(content_type)?)
+        .body(data)
+        .build();
+
+    Ok(response)
+}
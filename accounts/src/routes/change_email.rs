@@ -1,14 +1,23 @@
 use crate::{
+    account_has_permission,
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    client_ip::get_client_ip_address,
     config::CONFIG,
     database::DATABASE_POOL,
-    email::send_email,
-    get_decode_verify_and_return_session_token, is_account_admin_from_id,
-    models::{AdminEmailChangeRequest, BeginEmailChangeRequest, FinishEmailChangeRequest},
+    email::queue_templated_email,
+    get_decode_verify_and_return_session_token,
+    models::{
+        AdminEmailChangeRequest, AuditAction, BeginEmailChangeRequest, EmailTemplate,
+        FinishEmailChangeRequest, Permission, ResendVerificationResponse, RevertEmailChangeRequest,
+        VerificationFlow,
+    },
     prelude::*,
-    random::get_random_numbers,
-    string_to_email_placeholder,
+    random::get_random_string,
+    resend::start_resend_cooldown,
+    verification::{generate_verification_code, verification_code_expired},
 };
-use tide::{Response, StatusCode};
+use tera::Context;
+use tide::{convert::json, Response, StatusCode};
 use validator::Validate;
 
 pub async fn admin_email_change(mut req: tide::Request<()>) -> tide::Result {
@@ -43,11 +52,11 @@ pub async fn admin_email_change(mut req: tide::Request<()>) -> tide::Result {
 
     let account_id = session.account_id;
 
-    // CHECK IF USER IS ADMIN
+    // CHECK IF USER HAS PERMISSION TO CHANGE EMAILS
 
-    match is_account_admin_from_id(&account_id).await {
-        Ok(is_admin) => {
-            if !is_admin {
+    match account_has_permission(&account_id, Permission::AccountsChangeEmail).await {
+        Ok(has_permission) => {
+            if !has_permission {
                 let response = Response::new(StatusCode::Unauthorized);
                 return Ok(response);
             }
@@ -74,6 +83,26 @@ pub async fn admin_email_change(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     };
 
+    // GET THE CURRENT EMAIL OF THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            SELECT email
+            FROM accounts
+            WHERE id = $1;
+        "#,
+        &body.account_id
+    );
+
+    let previous_email = match query.fetch_optional(&mut *transaction).await? {
+        Some(result) => result.email,
+        None => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
     // CHANGE THE EMAIL
 
     let query = sqlx::query!(
@@ -94,6 +123,23 @@ pub async fn admin_email_change(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     }
 
+    // RECORD THE CHANGE IN THE AUDIT LOG
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: account_id,
+            target_id: body.account_id.to_owned(),
+            action: AuditAction::EmailChange,
+            diff: json!({
+                "before": { "email": previous_email },
+                "after": { "email": body.email },
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -129,6 +175,13 @@ pub async fn begin_email_change(mut req: tide::Request<()>) -> tide::Result {
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE EMAIL
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET ACCOUNT ID FROM TOKEN
@@ -153,8 +206,9 @@ pub async fn begin_email_change(mut req: tide::Request<()>) -> tide::Result {
 
     // GENERATE BOTH CODES FOR VERIFICATION
 
-    let original_email_verification_code = get_random_numbers(CONFIG.verification_code_length);
-    let new_email_verification_code = get_random_numbers(CONFIG.verification_code_length);
+    let original_email_verification_code =
+        generate_verification_code(VerificationFlow::EmailChange);
+    let new_email_verification_code = generate_verification_code(VerificationFlow::EmailChange);
 
     // GET TIMESTAMP
 
@@ -167,12 +221,14 @@ pub async fn begin_email_change(mut req: tide::Request<()>) -> tide::Result {
             UPDATE accounts 
             SET original_email_verification_code = $1, 
                 new_email_verification_code = $2, 
-                email_verification_codes_created_at = $3
-            WHERE id = $4;
+                email_verification_codes_created_at = $3,
+                new_email = $4
+            WHERE id = $5;
         "#,
         original_email_verification_code,
         new_email_verification_code,
         timestamp,
+        body.email,
         account_id,
     );
 
@@ -187,7 +243,7 @@ pub async fn begin_email_change(mut req: tide::Request<()>) -> tide::Result {
 
     let query = sqlx::query!(
         r#"
-            SELECT handle, email
+            SELECT handle, email, preferred_language
             FROM accounts 
             WHERE id = $1;
         "#,
@@ -199,44 +255,38 @@ pub async fn begin_email_change(mut req: tide::Request<()>) -> tide::Result {
     let handle = result.handle;
     let email = result.email;
 
-    // REPLACE VERIFICATION CODE PLACEHOLDER IN EMAIL BODY
-    // WITH THE ACTUAL VERIFICATION CODE
-
-    let verification_code_placeholder = string_to_email_placeholder("verification_code");
-    let handle_placeholder = string_to_email_placeholder("handle");
-    let new_email_placeholder = string_to_email_placeholder("new_email");
+    // FILL THE EMAIL TEMPLATES WITH THE ACTUAL VERIFICATION CODES
 
-    let original_body_with_placeholders_replaced = &CONFIG
-        .account_creation_verification_email_body
-        .replace(&handle_placeholder, &f!("@{}", &handle))
-        .replace(&new_email_placeholder, &body.email)
-        .replace(
-            &verification_code_placeholder,
-            &original_email_verification_code,
-        );
+    let mut original_context = Context::new();
+    original_context.insert("handle", &handle);
+    original_context.insert("new_email", &body.email);
+    original_context.insert("verification_code", &original_email_verification_code);
 
-    let new_body_with_placeholders_replaced = &CONFIG
-        .account_creation_verification_email_body
-        .replace(&handle_placeholder, &f!("@{}", &handle))
-        .replace(&verification_code_placeholder, &new_email_verification_code);
+    let mut new_context = Context::new();
+    new_context.insert("handle", &handle);
+    new_context.insert("verification_code", &new_email_verification_code);
 
     // SEND EMAIL TO ORIGINAL EMAIL
 
-    send_email(
+    queue_templated_email(
+        &mut transaction,
         &email,
-        &CONFIG.account_email_change_original_email_verification_subject,
-        original_body_with_placeholders_replaced,
-        CONFIG.account_email_change_original_email_verification_html,
-    )?;
+        EmailTemplate::EmailChangeOriginalEmailVerification,
+        result.preferred_language.as_deref(),
+        &original_context,
+    )
+    .await?;
 
     // SEND EMAIL TO NEW EMAIL
 
-    send_email(
+    queue_templated_email(
+        &mut transaction,
         &body.email,
-        &CONFIG.account_email_change_new_email_verification_subject,
-        new_body_with_placeholders_replaced,
-        CONFIG.account_email_change_new_email_verification_html,
-    )?;
+        EmailTemplate::EmailChangeNewEmailVerification,
+        result.preferred_language.as_deref(),
+        &new_context,
+    )
+    .await?;
 
     // FINALY COMMIT TRANSACTION
 
@@ -273,6 +323,13 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE EMAIL
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET ACCOUNT ID FROM TOKEN
@@ -285,7 +342,11 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
         r#"
             SELECT original_email_verification_code, 
                    new_email_verification_code, 
-                   email_verification_codes_created_at
+                   email_verification_codes_created_at,
+                   new_email,
+                   handle,
+                   email,
+                   preferred_language
             FROM accounts 
             WHERE id = $1;
         "#,
@@ -294,11 +355,16 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
 
     let result = query.fetch_one(&mut *transaction).await?;
 
+    let handle = result.handle;
+    let old_email = result.email;
+    let preferred_language = result.preferred_language;
+
     // GET CODES AND TIMESTAMP
 
     if result.email_verification_codes_created_at.is_none()
         || result.original_email_verification_code.is_none()
         || result.new_email_verification_code.is_none()
+        || result.new_email.is_none()
     {
         let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
@@ -307,14 +373,14 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
     let original_email_verification_code = result.original_email_verification_code.unwrap();
     let new_email_verification_code = result.new_email_verification_code.unwrap();
     let email_verification_codes_created_at = result.email_verification_codes_created_at.unwrap();
+    let new_email = result.new_email.unwrap();
 
-    // CHECK IF TIMESTAMP IS EXPIRED
+    // CHECK IF CODES ARE EXPIRED
 
-    let timestamp = chrono::Utc::now().naive_utc();
-
-    let timestamp_difference = timestamp - email_verification_codes_created_at;
-
-    if timestamp_difference < chrono::Duration::seconds(0) {
+    if verification_code_expired(
+        VerificationFlow::EmailChange,
+        email_verification_codes_created_at,
+    ) {
         let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
     }
@@ -328,6 +394,13 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     }
 
+    // THE CODES ONLY VERIFY THE ADDRESS THEY WERE SENT TO
+
+    if new_email != body.email {
+        let response = Response::new(StatusCode::Unauthorized);
+        return Ok(response);
+    }
+
     // SET CODES AND TIMESTAMP TO NULL
 
     let query = sqlx::query!(
@@ -335,7 +408,8 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
             UPDATE accounts 
             SET original_email_verification_code = NULL, 
                 new_email_verification_code = NULL, 
-                email_verification_codes_created_at = NULL
+                email_verification_codes_created_at = NULL,
+                new_email = NULL
             WHERE id = $1;
         "#,
         account_id,
@@ -357,7 +431,7 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
             SET email = $1
             WHERE id = $2;
         "#,
-        body.email,
+        new_email,
         account_id,
     );
 
@@ -387,6 +461,204 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     }
 
+    // STORE A TOKEN THAT LETS THE OLD ADDRESS UNDO THE CHANGE
+
+    let timestamp = chrono::Utc::now().naive_utc();
+    let revert_token = get_random_string(CONFIG.email_change_revert_token_length);
+    let expires_at = timestamp + chrono::Duration::hours(CONFIG.email_change_revert_hours);
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO email_change_reverts (
+                "token",
+                "account_id",
+                "old_email",
+                "new_email",
+                "created_at",
+                "expires_at"
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        revert_token,
+        account_id,
+        old_email,
+        new_email,
+        timestamp,
+        expires_at
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    // FILL THE EMAIL TEMPLATE WITH THE REVERT LINK
+
+    let mut context = Context::new();
+    context.insert("handle", &handle);
+    context.insert("new_email", &new_email);
+    context.insert(
+        "revert_link",
+        &f!("{}{}", CONFIG.email_change_revert_link, revert_token),
+    );
+    context.insert(
+        "expires_at",
+        &expires_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    );
+
+    // NOTIFY THE OLD EMAIL
+
+    queue_templated_email(
+        &mut transaction,
+        &old_email,
+        EmailTemplate::EmailChangeRevert,
+        preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE
+
+    Ok(Response::new(StatusCode::Ok))
+}
+
+pub async fn revert_email_change(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: RevertEmailChangeRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE REVERT TOKEN, THE LINK IS THE ONLY CREDENTIAL NEEDED
+    // SINCE WHOEVER CHANGED THE EMAIL MIGHT HOLD THE SESSIONS
+
+    let query = sqlx::query!(
+        r#"
+            SELECT account_id, old_email, new_email, expires_at
+            FROM email_change_reverts
+            WHERE token = $1
+            FOR UPDATE
+        "#,
+        body.token
+    );
+
+    let revert = match query.fetch_optional(&mut *transaction).await? {
+        Some(revert) => revert,
+        None => {
+            let response = Response::new(StatusCode::Unauthorized);
+            return Ok(response);
+        }
+    };
+
+    let timestamp = chrono::Utc::now().naive_utc();
+
+    if revert.expires_at <= timestamp {
+        let response = Response::new(StatusCode::Unauthorized);
+        return Ok(response);
+    }
+
+    // ONLY REVERT IF THE EMAIL IS STILL THE ONE THE TOKEN WAS ISSUED FOR
+
+    let query = sqlx::query!(
+        r#"
+            SELECT email
+            FROM accounts
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        revert.account_id
+    );
+
+    let account = query.fetch_one(&mut *transaction).await?;
+
+    if account.email != revert.new_email {
+        let response = Response::new(StatusCode::Conflict);
+        return Ok(response);
+    }
+
+    // THE OLD EMAIL MIGHT HAVE BEEN TAKEN BY ANOTHER ACCOUNT IN THE MEANTIME
+
+    let query = sqlx::query!(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM accounts WHERE email = $1 AND id != $2
+            ) AS "email_taken!"
+        "#,
+        revert.old_email,
+        revert.account_id
+    );
+
+    if query.fetch_one(&mut *transaction).await?.email_taken {
+        let response = Response::new(StatusCode::Conflict);
+        return Ok(response);
+    }
+
+    // RESTORE THE OLD EMAIL AND DROP ANY EMAIL CHANGE IN PROGRESS
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE accounts
+            SET email = $1,
+                original_email_verification_code = NULL,
+                new_email_verification_code = NULL,
+                email_verification_codes_created_at = NULL,
+                new_email = NULL
+            WHERE id = $2;
+        "#,
+        revert.old_email,
+        revert.account_id
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    // DELETE ALL SESSIONS FOR ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM sessions
+            WHERE account_id = $1;
+        "#,
+        revert.account_id
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    // EVERY REVERT TOKEN OF THE ACCOUNT IS NOW STALE
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM email_change_reverts
+            WHERE account_id = $1;
+        "#,
+        revert.account_id
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: revert.account_id.to_owned(),
+            target_id: revert.account_id,
+            action: AuditAction::EmailChangeRevert,
+            diff: json!({
+                "before": { "email": revert.new_email },
+                "after": { "email": revert.old_email },
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -395,3 +667,151 @@ pub async fn finish_email_change(mut req: tide::Request<()>) -> tide::Result {
 
     Ok(Response::new(StatusCode::Ok))
 }
+
+pub async fn resend_email_change(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE EMAIL
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
+    let session = session_token.session;
+
+    // GET ACCOUNT ID FROM TOKEN
+
+    let account_id = session.account_id;
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE PENDING VERIFICATION CODES AND THE ADDRESS THEY'RE FOR
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                handle,
+                email,
+                new_email,
+                preferred_language,
+                original_email_verification_code,
+                new_email_verification_code,
+                email_verification_codes_created_at
+            FROM accounts
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        account_id
+    );
+
+    let account = query.fetch_one(&mut *transaction).await?;
+
+    let (
+        new_email,
+        original_email_verification_code,
+        new_email_verification_code,
+        email_verification_codes_created_at,
+    ) = match (
+        account.new_email,
+        account.original_email_verification_code,
+        account.new_email_verification_code,
+        account.email_verification_codes_created_at,
+    ) {
+        (
+            Some(new_email),
+            Some(original_email_verification_code),
+            Some(new_email_verification_code),
+            Some(email_verification_codes_created_at),
+        ) if !verification_code_expired(
+            VerificationFlow::EmailChange,
+            email_verification_codes_created_at,
+        ) =>
+        {
+            (
+                new_email,
+                original_email_verification_code,
+                new_email_verification_code,
+                email_verification_codes_created_at,
+            )
+        }
+        _ => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // CHECK THE COOLDOWN OF THE ACCOUNT
+
+    if let Some(cooldown_seconds) = start_resend_cooldown(
+        &mut transaction,
+        VerificationFlow::EmailChange,
+        &account_id,
+        email_verification_codes_created_at,
+    )
+    .await?
+    {
+        let response = Response::builder(StatusCode::TooManyRequests)
+            .body(json!(ResendVerificationResponse { cooldown_seconds }))
+            .build();
+        return Ok(response);
+    }
+
+    // FILL THE EMAIL TEMPLATES WITH THE SAME VERIFICATION CODES
+
+    let mut original_context = Context::new();
+    original_context.insert("handle", &account.handle);
+    original_context.insert("new_email", &new_email);
+    original_context.insert("verification_code", &original_email_verification_code);
+
+    let mut new_context = Context::new();
+    new_context.insert("handle", &account.handle);
+    new_context.insert("verification_code", &new_email_verification_code);
+
+    // SEND EMAIL TO ORIGINAL EMAIL
+
+    queue_templated_email(
+        &mut transaction,
+        &account.email,
+        EmailTemplate::EmailChangeOriginalEmailVerification,
+        account.preferred_language.as_deref(),
+        &original_context,
+    )
+    .await?;
+
+    // SEND EMAIL TO NEW EMAIL
+
+    queue_templated_email(
+        &mut transaction,
+        &new_email,
+        EmailTemplate::EmailChangeNewEmailVerification,
+        account.preferred_language.as_deref(),
+        &new_context,
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE, WITH THE TIME UNTIL THE NEXT RESEND IS ALLOWED
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(ResendVerificationResponse {
+            cooldown_seconds: CONFIG.verification_resend_cooldown_seconds,
+        }))
+        .build();
+
+    Ok(response)
+}
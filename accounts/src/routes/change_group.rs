@@ -1,10 +1,16 @@
-use tide::{Response, StatusCode};
+use tide::{convert::json, Response, StatusCode};
 use validator::Validate;
 
 use crate::{
-    database::DATABASE_POOL, get_decode_verify_and_return_session_token, is_account_admin_from_id,
-    models::AdminGroupChangeRequest,
+    account_has_permission,
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    client_ip::get_client_ip_address,
+    database::DATABASE_POOL,
+    get_decode_verify_and_return_session_token,
+    models::{AdminGroupChangeRequest, AuditAction, EmailTemplate, Permission},
+    notification::queue_security_notification,
 };
+use tera::Context;
 
 pub async fn admin_group_change(mut req: tide::Request<()>) -> tide::Result {
     // GET REQUEST BODY AND VALIDATE IT
@@ -38,11 +44,11 @@ pub async fn admin_group_change(mut req: tide::Request<()>) -> tide::Result {
 
     let account_id = session.account_id;
 
-    // CHECK IF USER IS ADMIN
+    // CHECK IF USER HAS PERMISSION TO ASSIGN GROUPS
 
-    match is_account_admin_from_id(&account_id).await {
-        Ok(is_admin) => {
-            if !is_admin {
+    match account_has_permission(&account_id, Permission::GroupsAssign).await {
+        Ok(has_permission) => {
+            if !has_permission {
                 let response = Response::new(StatusCode::Unauthorized);
                 return Ok(response);
             }
@@ -53,6 +59,59 @@ pub async fn admin_group_change(mut req: tide::Request<()>) -> tide::Result {
         }
     }
 
+    // CHECK IF THE GROUP EXISTS AND IF BOTH THE NEW AND THE CURRENT GROUP
+    // OF THE TARGET ONLY GRANT PERMISSIONS THE USER ALREADY HAS,
+    // SO NOBODY CAN PROMOTE OR DEMOTE ACCOUNTS ABOVE THEIR OWN LEVEL
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                (SELECT "group" FROM accounts WHERE id = $3) AS previous_group,
+                EXISTS(
+                    SELECT 1 FROM roles WHERE name = $1
+                ) AS group_exists,
+                NOT EXISTS(
+                    SELECT permission
+                    FROM role_permissions
+                    WHERE role = $1
+                        OR role = (SELECT "group" FROM accounts WHERE id = $3)
+                    EXCEPT
+                    SELECT role_permissions.permission
+                    FROM accounts
+                    JOIN role_permissions ON role_permissions.role = accounts."group"
+                    WHERE accounts.id = $2
+                ) AS within_own_permissions
+        "#,
+        &body.group,
+        &account_id,
+        &body.account_id
+    );
+
+    let result = query.fetch_one(&mut *transaction).await?;
+
+    let previous_group = match result.previous_group {
+        Some(previous_group) => previous_group,
+        None => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    match (result.group_exists, result.within_own_permissions) {
+        (Some(true), Some(true)) => (),
+        (Some(false), _) => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+        _ => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::Unauthorized);
+            return Ok(response);
+        }
+    }
+
     // CHANGE THE GROUP
 
     let query = sqlx::query!(
@@ -61,7 +120,7 @@ pub async fn admin_group_change(mut req: tide::Request<()>) -> tide::Result {
             SET "group" = $1
             WHERE id = $2;
         "#,
-        &body.group.to_string(),
+        &body.group,
         &body.account_id
     );
 
@@ -73,6 +132,37 @@ pub async fn admin_group_change(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     }
 
+    // RECORD THE CHANGE IN THE AUDIT LOG
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: account_id,
+            target_id: body.account_id.to_owned(),
+            action: AuditAction::GroupChange,
+            diff: json!({
+                "before": { "group": previous_group },
+                "after": { "group": body.group },
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
+    // LET THE OWNER KNOW ABOUT THE CHANGE
+
+    let mut context = Context::new();
+    context.insert("previous_group", &previous_group);
+    context.insert("group", &body.group);
+
+    queue_security_notification(
+        &mut transaction,
+        &body.account_id,
+        EmailTemplate::GroupChanged,
+        context,
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -1,7 +1,7 @@
 use crate::{
-    database::DATABASE_POOL, get_decode_verify_and_return_session_token, is_account_admin_from_id, models::{AccountInfoChangeRequest, AdminAccountInfoChangeRequest}, sanitize_handle
+    account_has_permission, audit::{record_audit_log_entry, NewAuditLogEntry}, client_ip::get_client_ip_address, database::DATABASE_POOL, get_decode_verify_and_return_session_token, models::{AccountInfoChangeRequest, AdminAccountInfoChangeRequest, AuditAction, Permission}, sanitize_handle
 };
-use tide::{Response, StatusCode};
+use tide::{convert::json, Response, StatusCode};
 use validator::Validate;
 
 pub async fn admin_info_change(mut req: tide::Request<()>) -> tide::Result {
@@ -15,21 +15,22 @@ pub async fn admin_info_change(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     };
 
-    // CHECK IF THE REQUESTER IS AN ADMIN
+    // CHECK IF THE REQUESTER HAS PERMISSION TO EDIT ACCOUNTS
 
-    match get_decode_verify_and_return_session_token(&req).await {
+    let actor_id = match get_decode_verify_and_return_session_token(&req).await {
         Ok(session_token) => {
-            if !is_account_admin_from_id(&session_token.session.account_id).await.unwrap_or(false) {
+            if !account_has_permission(&session_token.session.account_id, Permission::AccountsEdit).await.unwrap_or(false) {
                 let response = Response::new(StatusCode::Unauthorized);
                 return Ok(response);
             }
+            session_token.session.account_id
         },
         Err(err) => {
             let mut response = Response::new(StatusCode::Unauthorized);
             response.set_error(err);
             return Ok(response);
         }
-    }
+    };
 
     let mut info_to_change = body.info_to_change;
 
@@ -51,6 +52,9 @@ pub async fn admin_info_change(mut req: tide::Request<()>) -> tide::Result {
     info_to_change.country_code = info_to_change
         .country_code
         .map(|country_code| country_code.to_lowercase().trim().to_string());
+    info_to_change.preferred_language = info_to_change
+        .preferred_language
+        .map(|preferred_language| preferred_language.to_lowercase().trim().to_string());
 
     // BEGIN DATABASE TRANSACTION
 
@@ -60,6 +64,27 @@ pub async fn admin_info_change(mut req: tide::Request<()>) -> tide::Result {
 
     let account_id = body.account_id;
 
+    // GET THE CURRENT INFO FOR THE AUDIT LOG
+
+    let query = sqlx::query!(
+        r#"
+            SELECT handle, name, gender, country_code, new_device_sign_in_email, preferred_language
+            FROM accounts
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        account_id
+    );
+
+    let previous_info = match query.fetch_optional(&mut *transaction).await? {
+        Some(previous_info) => previous_info,
+        None => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
     // UPDATE INFO THAT IS NOT NONE IN THE DATABASE
 
     let query = sqlx::query!(
@@ -69,23 +94,58 @@ pub async fn admin_info_change(mut req: tide::Request<()>) -> tide::Result {
                 handle = COALESCE($1, handle),
                 name = COALESCE($2, name),
                 gender = COALESCE($3, gender),
-                country_code = COALESCE($4, country_code)
-            WHERE id = $5
+                country_code = COALESCE($4, country_code),
+                new_device_sign_in_email = COALESCE($5, new_device_sign_in_email),
+                preferred_language = COALESCE($6, preferred_language)
+            WHERE id = $7
+            RETURNING handle, name, gender, country_code, new_device_sign_in_email, preferred_language
         "#,
         info_to_change.handle,
         info_to_change.name,
         info_to_change.gender.map(|gender| gender.to_string()),
         info_to_change.country_code,
+        info_to_change.new_device_sign_in_email,
+        info_to_change.preferred_language,
         account_id
     );
 
-    let result = query.execute(&mut *transaction).await?;
-
-    if result.rows_affected() != 1 {
-        let response = Response::new(StatusCode::InternalServerError);
-        return Ok(response);
+    let new_info = match query.fetch_optional(&mut *transaction).await? {
+        Some(new_info) => new_info,
+        None => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
     };
 
+    // RECORD THE CHANGE IN THE AUDIT LOG
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id,
+            target_id: account_id,
+            action: AuditAction::InfoChange,
+            diff: json!({
+                "before": {
+                    "handle": previous_info.handle,
+                    "name": previous_info.name,
+                    "gender": previous_info.gender,
+                    "country_code": previous_info.country_code,
+                    "new_device_sign_in_email": previous_info.new_device_sign_in_email,
+                },
+                "after": {
+                    "handle": new_info.handle,
+                    "name": new_info.name,
+                    "gender": new_info.gender,
+                    "country_code": new_info.country_code,
+                    "new_device_sign_in_email": new_info.new_device_sign_in_email,
+                },
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -124,6 +184,9 @@ pub async fn info_change(mut req: tide::Request<()>) -> tide::Result {
     body.country_code = body
         .country_code
         .map(|country_code| country_code.to_lowercase().trim().to_string());
+    body.preferred_language = body
+        .preferred_language
+        .map(|preferred_language| preferred_language.to_lowercase().trim().to_string());
 
     // BEGIN DATABASE TRANSACTION
 
@@ -146,6 +209,26 @@ pub async fn info_change(mut req: tide::Request<()>) -> tide::Result {
 
     let account_id = session.account_id;
 
+    // CHANGES MADE WHILE IMPERSONATING GO TO THE AUDIT LOG,
+    // SO GET THE CURRENT INFO FIRST
+
+    let previous_info = match &session_token.impersonator_id {
+        Some(_) => {
+            let query = sqlx::query!(
+                r#"
+                    SELECT handle, name, gender, country_code, new_device_sign_in_email, preferred_language
+                    FROM accounts
+                    WHERE id = $1
+                    FOR UPDATE
+                "#,
+                account_id
+            );
+
+            query.fetch_optional(&mut *transaction).await?
+        }
+        None => None,
+    };
+
     // UPDATE INFO THAT IS NOT NONE IN THE DATABASE
 
     let query = sqlx::query!(
@@ -155,23 +238,64 @@ pub async fn info_change(mut req: tide::Request<()>) -> tide::Result {
                 handle = COALESCE($1, handle),
                 name = COALESCE($2, name),
                 gender = COALESCE($3, gender),
-                country_code = COALESCE($4, country_code)
-            WHERE id = $5
+                country_code = COALESCE($4, country_code),
+                new_device_sign_in_email = COALESCE($5, new_device_sign_in_email),
+                preferred_language = COALESCE($6, preferred_language)
+            WHERE id = $7
+            RETURNING handle, name, gender, country_code, new_device_sign_in_email, preferred_language
         "#,
         body.handle,
         body.name,
         body.gender.map(|gender| gender.to_string()),
         body.country_code,
+        body.new_device_sign_in_email,
+        body.preferred_language,
         account_id
     );
 
-    let result = query.execute(&mut *transaction).await?;
-
-    if result.rows_affected() != 1 {
-        let response = Response::new(StatusCode::InternalServerError);
-        return Ok(response);
+    let new_info = match query.fetch_optional(&mut *transaction).await? {
+        Some(new_info) => new_info,
+        None => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
     };
 
+    // RECORD THE CHANGE IN THE AUDIT LOG IF IT WAS MADE BY AN IMPERSONATOR
+
+    if let (Some(impersonator_id), Some(previous_info)) =
+        (session_token.impersonator_id, previous_info)
+    {
+        record_audit_log_entry(
+            &mut transaction,
+            NewAuditLogEntry {
+                actor_id: impersonator_id,
+                target_id: account_id,
+                action: AuditAction::InfoChange,
+                diff: json!({
+                    "before": {
+                        "handle": previous_info.handle,
+                        "name": previous_info.name,
+                        "gender": previous_info.gender,
+                        "country_code": previous_info.country_code,
+                        "new_device_sign_in_email": previous_info.new_device_sign_in_email,
+                        "preferred_language": previous_info.preferred_language,
+                    },
+                    "after": {
+                        "handle": new_info.handle,
+                        "name": new_info.name,
+                        "gender": new_info.gender,
+                        "country_code": new_info.country_code,
+                        "new_device_sign_in_email": new_info.new_device_sign_in_email,
+                        "preferred_language": new_info.preferred_language,
+                    },
+                }),
+                ip_address: get_client_ip_address(&req),
+            },
+        )
+        .await?;
+    }
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -1,15 +1,20 @@
-use tide::{Response, StatusCode};
+use tide::{convert::json, Response, StatusCode};
 use validator::Validate;
 
 use crate::{
+    account_has_permission,
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    client_ip::get_client_ip_address,
     config::CONFIG,
     database::DATABASE_POOL,
-    email::send_email,
-    encryption, get_decode_verify_and_return_session_token, is_account_admin_from_id,
-    models::{AdminPasswordChangeRequest, FinishPasswordChangeRequest, BeginForgotPasswordRequest, FinishForgotPasswordRequest},
-    random::get_random_numbers,
-    string_to_email_placeholder,
+    email::queue_templated_email,
+    encryption, get_decode_verify_and_return_session_token,
+    notification::queue_security_notification,
+    models::{AdminPasswordChangeRequest, AuditAction, EmailTemplate, FinishPasswordChangeRequest, BeginForgotPasswordRequest, FinishForgotPasswordRequest, Permission, ResendForgotPasswordRequest, ResendVerificationResponse, VerificationFlow},
+    resend::start_resend_cooldown,
+    verification::{generate_verification_code, verification_code_expired},
 };
+use tera::Context;
 
 pub async fn admin_password_change(mut req: tide::Request<()>) -> tide::Result {
     // GET REQUEST BODY AND VALIDATE IT
@@ -43,11 +48,11 @@ pub async fn admin_password_change(mut req: tide::Request<()>) -> tide::Result {
 
     let account_id = session.account_id;
 
-    // CHECK IF USER IS ADMIN
+    // CHECK IF USER HAS PERMISSION TO CHANGE PASSWORDS
 
-    match is_account_admin_from_id(&account_id).await {
-        Ok(is_admin) => {
-            if !is_admin {
+    match account_has_permission(&account_id, Permission::AccountsChangePassword).await {
+        Ok(has_permission) => {
+            if !has_permission {
                 let response = Response::new(StatusCode::Unauthorized);
                 return Ok(response);
             }
@@ -82,6 +87,33 @@ pub async fn admin_password_change(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     }
 
+    // RECORD THE CHANGE IN THE AUDIT LOG, WITHOUT THE PASSWORD ITSELF
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: account_id,
+            target_id: body.account_id.to_owned(),
+            action: AuditAction::PasswordChange,
+            diff: json!({
+                "before": { "password": "[redacted]" },
+                "after": { "password": "[redacted]" },
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
+    // LET THE OWNER KNOW ABOUT THE CHANGE
+
+    queue_security_notification(
+        &mut transaction,
+        &body.account_id,
+        EmailTemplate::PasswordChanged,
+        Context::new(),
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -108,7 +140,7 @@ pub async fn begin_forgot_password(mut req: tide::Request<()>) -> tide::Result {
 
     // GENERATE VERIFICATION CODE
 
-    let verification_code = get_random_numbers(CONFIG.verification_code_length);
+    let verification_code = generate_verification_code(VerificationFlow::ForgotPassword);
 
     // GET TIMESTAMP
 
@@ -121,11 +153,13 @@ pub async fn begin_forgot_password(mut req: tide::Request<()>) -> tide::Result {
             UPDATE accounts
             SET 
                 new_password_verification_code = $1, 
-                new_password_verification_code_created_at = $2
-            WHERE email = $3;
+                new_password_verification_code_created_at = $2,
+                new_password_verification_flow = $3
+            WHERE email = $4;
         "#,
         &verification_code,
         &timestamp,
+        VerificationFlow::ForgotPassword.to_string(),
         &body.email
     );
 
@@ -141,7 +175,7 @@ pub async fn begin_forgot_password(mut req: tide::Request<()>) -> tide::Result {
 
     let query = sqlx::query!(
         r#"
-            SELECT handle
+            SELECT handle, preferred_language
             FROM accounts 
             WHERE email = $1;
         "#,
@@ -150,26 +184,22 @@ pub async fn begin_forgot_password(mut req: tide::Request<()>) -> tide::Result {
 
     let result = query.fetch_one(&mut *transaction).await?;
 
-    let handle = result.handle;
-
-    // REPLACE VERIFICATION CODE PLACEHOLDER IN EMAIL BODY
+    // FILL THE EMAIL TEMPLATE WITH THE ACTUAL VERIFICATION CODE
 
-    let verification_code_placeholder = string_to_email_placeholder("verification_code");
-    let handle_placeholder = string_to_email_placeholder("handle");
-
-    let body_with_placeholders_replaced = CONFIG
-        .account_forgot_password_verification_body
-        .replace(&verification_code_placeholder, &verification_code)
-        .replace(&handle_placeholder, &handle);
+    let mut context = Context::new();
+    context.insert("handle", &result.handle);
+    context.insert("verification_code", &verification_code);
 
     // SEND EMAIL
 
-    send_email(
+    queue_templated_email(
+        &mut transaction,
         &body.email,
-        &CONFIG.account_forgot_password_verification_subject,
-        &body_with_placeholders_replaced,
-        CONFIG.account_forgot_password_verification_html,
-    )?;
+        EmailTemplate::ForgotPasswordVerification,
+        result.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
 
     // FINALY COMMIT TRANSACTION
 
@@ -200,7 +230,11 @@ pub async fn finish_forgot_password(mut req: tide::Request<()>) -> tide::Result
 
     let query = sqlx::query!(
         r#"
-            SELECT new_password_verification_code, new_password_verification_code_created_at
+            SELECT
+                id,
+                new_password_verification_code,
+                new_password_verification_code_created_at,
+                new_password_verification_flow
             FROM accounts
             WHERE email = $1;
         "#,
@@ -209,12 +243,18 @@ pub async fn finish_forgot_password(mut req: tide::Request<()>) -> tide::Result
 
     let result = query.fetch_one(&mut *transaction).await?;
 
+    let account_id = result.id;
     let verification_code = result.new_password_verification_code;
     let verification_code_created_at = result.new_password_verification_code_created_at;
+    let verification_flow = result.new_password_verification_flow;
 
-    // CHECK IF VERIFICATION CODE IS VALID
+    // CHECK IF VERIFICATION CODE IS VALID, A CODE ISSUED BY
+    // THE OTHER PASSWORD FLOW CAN'T BE USED HERE
 
-    if verification_code.is_none() || verification_code_created_at.is_none() {
+    if verification_code.is_none()
+        || verification_code_created_at.is_none()
+        || verification_flow != Some(VerificationFlow::ForgotPassword.to_string())
+    {
         transaction.rollback().await?;
         let response = Response::new(StatusCode::NotFound);
         return Ok(response);
@@ -223,11 +263,9 @@ pub async fn finish_forgot_password(mut req: tide::Request<()>) -> tide::Result
     let verification_code = verification_code.unwrap();
     let verification_code_created_at = verification_code_created_at.unwrap();
 
-    let timestamp = chrono::Utc::now().naive_utc();
-
-    let timestamp_difference = timestamp - verification_code_created_at;
+    // CHECK IF VERIFICATION CODE IS EXPIRED
 
-    if timestamp_difference < chrono::Duration::seconds(0) {
+    if verification_code_expired(VerificationFlow::ForgotPassword, verification_code_created_at) {
         let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
     }
@@ -251,7 +289,8 @@ pub async fn finish_forgot_password(mut req: tide::Request<()>) -> tide::Result
             SET 
                 password = $1, 
                 new_password_verification_code = NULL, 
-                new_password_verification_code_created_at = NULL
+                new_password_verification_code_created_at = NULL,
+                new_password_verification_flow = NULL
             WHERE email = $2;
         "#,
         &encrypted_password,
@@ -282,6 +321,16 @@ pub async fn finish_forgot_password(mut req: tide::Request<()>) -> tide::Result
 
     query.execute(&mut *transaction).await?;
 
+    // LET THE OWNER KNOW ABOUT THE CHANGE
+
+    queue_security_notification(
+        &mut transaction,
+        &account_id,
+        EmailTemplate::PasswordChanged,
+        Context::new(),
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -307,6 +356,13 @@ pub async fn begin_password_change(req: tide::Request<()>) -> tide::Result {
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE PASSWORD
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET ACCOUNT ID FROM TOKEN
@@ -315,7 +371,7 @@ pub async fn begin_password_change(req: tide::Request<()>) -> tide::Result {
 
     // GENERATE VERIFICATION CODE
 
-    let verification_code = get_random_numbers(CONFIG.verification_code_length);
+    let verification_code = generate_verification_code(VerificationFlow::PasswordChange);
 
     // GET TIMESTAMP
 
@@ -329,11 +385,13 @@ pub async fn begin_password_change(req: tide::Request<()>) -> tide::Result {
             UPDATE accounts
             SET 
                 new_password_verification_code = $1, 
-                new_password_verification_code_created_at = $2
-            WHERE id = $3;
+                new_password_verification_code_created_at = $2,
+                new_password_verification_flow = $3
+            WHERE id = $4;
         "#,
         &verification_code,
         &timestamp,
+        VerificationFlow::PasswordChange.to_string(),
         &account_id
     );
 
@@ -349,7 +407,7 @@ pub async fn begin_password_change(req: tide::Request<()>) -> tide::Result {
 
     let query = sqlx::query!(
         r#"
-            SELECT handle, email
+            SELECT handle, email, preferred_language
             FROM accounts 
             WHERE id = $1;
         "#,
@@ -358,28 +416,22 @@ pub async fn begin_password_change(req: tide::Request<()>) -> tide::Result {
 
     let result = query.fetch_one(&mut *transaction).await?;
 
-    let handle = result.handle;
-    let email = result.email;
-
-    // REPLACE VERIFICATION CODE PLACEHOLDER IN EMAIL BODY
-    // WITH THE ACTUAL VERIFICATION CODE
+    // FILL THE EMAIL TEMPLATE WITH THE ACTUAL VERIFICATION CODE
 
-    let verification_code_placeholder = string_to_email_placeholder("verification_code");
-    let handle_placeholder = string_to_email_placeholder("handle");
-
-    let body_with_placeholders_replaced = CONFIG
-        .account_creation_verification_email_body
-        .replace(&verification_code_placeholder, &verification_code)
-        .replace(&handle_placeholder, &handle);
+    let mut context = Context::new();
+    context.insert("handle", &result.handle);
+    context.insert("verification_code", &verification_code);
 
     // SEND EMAIL
 
-    send_email(
-        &email,
-        &CONFIG.account_password_change_verification_subject,
-        &body_with_placeholders_replaced,
-        CONFIG.account_password_change_verification_html,
-    )?;
+    queue_templated_email(
+        &mut transaction,
+        &result.email,
+        EmailTemplate::PasswordChangeVerification,
+        result.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
 
     // FINALY COMMIT TRANSACTION
 
@@ -416,6 +468,13 @@ pub async fn finish_password_change(mut req: tide::Request<()>) -> tide::Result
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE PASSWORD
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET ACCOUNT ID FROM TOKEN
@@ -426,7 +485,10 @@ pub async fn finish_password_change(mut req: tide::Request<()>) -> tide::Result
 
     let query = sqlx::query!(
         r#"
-            SELECT new_password_verification_code, new_password_verification_code_created_at
+            SELECT
+                new_password_verification_code,
+                new_password_verification_code_created_at,
+                new_password_verification_flow
             FROM accounts
             WHERE id = $1;
         "#,
@@ -437,10 +499,15 @@ pub async fn finish_password_change(mut req: tide::Request<()>) -> tide::Result
 
     let verification_code = result.new_password_verification_code;
     let verification_code_created_at = result.new_password_verification_code_created_at;
+    let verification_flow = result.new_password_verification_flow;
 
-    // CHECK IF VERIFICATION CODE IS VALID
+    // CHECK IF VERIFICATION CODE IS VALID, A CODE ISSUED BY
+    // THE OTHER PASSWORD FLOW CAN'T BE USED HERE
 
-    if verification_code.is_none() || verification_code_created_at.is_none() {
+    if verification_code.is_none()
+        || verification_code_created_at.is_none()
+        || verification_flow != Some(VerificationFlow::PasswordChange.to_string())
+    {
         transaction.rollback().await?;
         let response = Response::new(StatusCode::NotFound);
         return Ok(response);
@@ -449,11 +516,9 @@ pub async fn finish_password_change(mut req: tide::Request<()>) -> tide::Result
     let verification_code = verification_code.unwrap();
     let verification_code_created_at = verification_code_created_at.unwrap();
 
-    let timestamp = chrono::Utc::now().naive_utc();
-
-    let timestamp_difference = timestamp - verification_code_created_at;
+    // CHECK IF VERIFICATION CODE IS EXPIRED
 
-    if timestamp_difference < chrono::Duration::seconds(0) {
+    if verification_code_expired(VerificationFlow::PasswordChange, verification_code_created_at) {
         let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
     }
@@ -477,7 +542,8 @@ pub async fn finish_password_change(mut req: tide::Request<()>) -> tide::Result
             SET 
                 password = $1, 
                 new_password_verification_code = NULL, 
-                new_password_verification_code_created_at = NULL
+                new_password_verification_code_created_at = NULL,
+                new_password_verification_flow = NULL
             WHERE id = $2;
         "#,
         &encrypted_password,
@@ -510,6 +576,16 @@ pub async fn finish_password_change(mut req: tide::Request<()>) -> tide::Result
         return Ok(response);
     }
 
+    // LET THE OWNER KNOW ABOUT THE CHANGE
+
+    queue_security_notification(
+        &mut transaction,
+        &account_id,
+        EmailTemplate::PasswordChanged,
+        Context::new(),
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
@@ -518,3 +594,239 @@ pub async fn finish_password_change(mut req: tide::Request<()>) -> tide::Result
 
     Ok(Response::new(StatusCode::Ok))
 }
+
+pub async fn resend_forgot_password(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: ResendForgotPasswordRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE PENDING VERIFICATION CODE OF THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                id,
+                handle,
+                email,
+                preferred_language,
+                new_password_verification_code,
+                new_password_verification_code_created_at,
+                new_password_verification_flow
+            FROM accounts
+            WHERE email = $1
+            FOR UPDATE
+        "#,
+        body.email
+    );
+
+    let account = match query.fetch_optional(&mut *transaction).await? {
+        Some(account) => account,
+        None => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    let (verification_code, verification_code_created_at) = match (
+        account.new_password_verification_code,
+        account.new_password_verification_code_created_at,
+        account.new_password_verification_flow,
+    ) {
+        (Some(verification_code), Some(verification_code_created_at), Some(verification_flow))
+            if verification_flow == VerificationFlow::ForgotPassword.to_string()
+                && !verification_code_expired(
+                    VerificationFlow::ForgotPassword,
+                    verification_code_created_at,
+                ) =>
+        {
+            (verification_code, verification_code_created_at)
+        }
+        _ => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // CHECK THE COOLDOWN OF THE ACCOUNT, SHARED WITH THE PASSWORD CHANGE FLOW
+    // SINCE BOTH SEND THE SAME CODE
+
+    if let Some(cooldown_seconds) = start_resend_cooldown(
+        &mut transaction,
+        VerificationFlow::PasswordChange,
+        &account.id,
+        verification_code_created_at,
+    )
+    .await?
+    {
+        let response = Response::builder(StatusCode::TooManyRequests)
+            .body(json!(ResendVerificationResponse { cooldown_seconds }))
+            .build();
+        return Ok(response);
+    }
+
+    // FILL THE EMAIL TEMPLATE WITH THE SAME VERIFICATION CODE
+
+    let mut context = Context::new();
+    context.insert("handle", &account.handle);
+    context.insert("verification_code", &verification_code);
+
+    // SEND EMAIL
+
+    queue_templated_email(
+        &mut transaction,
+        &account.email,
+        EmailTemplate::ForgotPasswordVerification,
+        account.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE, WITH THE TIME UNTIL THE NEXT RESEND IS ALLOWED
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(ResendVerificationResponse {
+            cooldown_seconds: CONFIG.verification_resend_cooldown_seconds,
+        }))
+        .build();
+
+    Ok(response)
+}
+
+pub async fn resend_password_change(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE PASSWORD
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
+    let session = session_token.session;
+
+    // GET ACCOUNT ID FROM TOKEN
+
+    let account_id = session.account_id;
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE PENDING VERIFICATION CODE OF THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                id,
+                handle,
+                email,
+                preferred_language,
+                new_password_verification_code,
+                new_password_verification_code_created_at,
+                new_password_verification_flow
+            FROM accounts
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        account_id
+    );
+
+    let account = match query.fetch_optional(&mut *transaction).await? {
+        Some(account) => account,
+        None => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    let (verification_code, verification_code_created_at) = match (
+        account.new_password_verification_code,
+        account.new_password_verification_code_created_at,
+        account.new_password_verification_flow,
+    ) {
+        (Some(verification_code), Some(verification_code_created_at), Some(verification_flow))
+            if verification_flow == VerificationFlow::PasswordChange.to_string()
+                && !verification_code_expired(
+                    VerificationFlow::PasswordChange,
+                    verification_code_created_at,
+                ) =>
+        {
+            (verification_code, verification_code_created_at)
+        }
+        _ => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // CHECK THE COOLDOWN OF THE ACCOUNT, SHARED WITH THE FORGOT PASSWORD FLOW
+    // SINCE BOTH SEND THE SAME CODE
+
+    if let Some(cooldown_seconds) = start_resend_cooldown(
+        &mut transaction,
+        VerificationFlow::PasswordChange,
+        &account.id,
+        verification_code_created_at,
+    )
+    .await?
+    {
+        let response = Response::builder(StatusCode::TooManyRequests)
+            .body(json!(ResendVerificationResponse { cooldown_seconds }))
+            .build();
+        return Ok(response);
+    }
+
+    // FILL THE EMAIL TEMPLATE WITH THE SAME VERIFICATION CODE
+
+    let mut context = Context::new();
+    context.insert("handle", &account.handle);
+    context.insert("verification_code", &verification_code);
+
+    // SEND EMAIL
+
+    queue_templated_email(
+        &mut transaction,
+        &account.email,
+        EmailTemplate::PasswordChangeVerification,
+        account.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE, WITH THE TIME UNTIL THE NEXT RESEND IS ALLOWED
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(ResendVerificationResponse {
+            cooldown_seconds: CONFIG.verification_resend_cooldown_seconds,
+        }))
+        .build();
+
+    Ok(response)
+}
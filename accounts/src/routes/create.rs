@@ -1,10 +1,15 @@
 use crate::{
-    config::CONFIG, database::DATABASE_POOL, email::send_email, encryption, models::{
+    config::CONFIG, database::DATABASE_POOL, email::queue_templated_email, encryption, models::{
         Account, AccountCreationVerification, BeginAccountCreationRequest, ConflictString,
-        FinishAccountCreationRequest, Group,
-    }, prelude::*, random::{get_random_numbers, get_random_string}, sanitize_handle, string_to_email_placeholder
+        EmailTemplate, FinishAccountCreationRequest, ResendAccountCreationRequest,
+        ResendVerificationResponse, VerificationFlow,
+    }, random::get_random_string, resend::start_resend_cooldown, sanitize_handle,
+    verification::{
+        generate_verification_code, verification_code_expired, verification_code_format,
+    },
 };
 use chrono::Utc;
+use tera::Context;
 use tide::{convert::json, Response, StatusCode};
 use validator::Validate;
 
@@ -82,9 +87,86 @@ pub async fn begin_account_creation(mut req: tide::Request<()>) -> tide::Result
         }
     }
 
+    // IN INVITE ONLY MODE A VALID INVITE CODE IS REQUIRED
+
+    let invite_code = if CONFIG.invite_only_registration {
+        let invite_code = match body.invite_code {
+            Some(invite_code) => invite_code,
+            None => {
+                transaction.rollback().await?;
+                let response = Response::new(StatusCode::Forbidden);
+                return Ok(response);
+            }
+        };
+
+        let query = sqlx::query!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1
+                FROM invites
+                WHERE code = $1
+                    AND (max_uses IS NULL OR uses < max_uses)
+                    AND (expires_at IS NULL OR expires_at > $2)
+            ) AS "is_valid!"
+            "#,
+            &invite_code,
+            Utc::now().naive_utc()
+        );
+
+        if !query.fetch_one(&mut *transaction).await?.is_valid {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::Forbidden);
+            return Ok(response);
+        }
+
+        Some(invite_code)
+    } else {
+        None
+    };
+
+    // THE NEWEST SIGNUP OF AN EMAIL REPLACES THE PENDING ONE, ONLY THE OWNER OF
+    // THE ADDRESS CAN FINISH IT, AND A HANDLE IS RELEASED ONCE ITS SIGNUP EXPIRES
+
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM account_creation_verifications
+        WHERE email = $1 OR (handle = $2 AND verification_code_created_at <= $3)
+        "#,
+        &body.email,
+        &body.handle,
+        Utc::now().naive_utc() - verification_code_format(VerificationFlow::AccountCreation).ttl
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    // CHECK IF THE HANDLE IS HELD BY THE PENDING SIGNUP OF ANOTHER EMAIL
+
+    let query = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM account_creation_verifications WHERE handle = $1
+        ) AS "handle_pending!"
+        "#,
+        &body.handle
+    );
+
+    if query.fetch_one(&mut *transaction).await?.handle_pending {
+        transaction.rollback().await?;
+
+        let conflict_string = ConflictString {
+            conflict: "handle".to_string(),
+        };
+
+        let response = Response::builder(StatusCode::Conflict)
+            .body(json!(conflict_string))
+            .build();
+
+        return Ok(response);
+    }
+
     // GENERATE VERIFICATION CODE
 
-    let verification_code = get_random_numbers(CONFIG.verification_code_length);
+    let verification_code = generate_verification_code(VerificationFlow::AccountCreation);
 
     // INSERT ACCOUNT CREATION VERIFICATION QUERY
 
@@ -93,6 +175,7 @@ pub async fn begin_account_creation(mut req: tide::Request<()>) -> tide::Result
         handle: body.handle,
         verification_code,
         verification_code_created_at: Utc::now().naive_utc(),
+        invite_code,
     };
 
     let query = sqlx::query!(
@@ -101,43 +184,46 @@ pub async fn begin_account_creation(mut req: tide::Request<()>) -> tide::Result
             "email",
             "handle",
             "verification_code",
-            "verification_code_created_at"
+            "verification_code_created_at",
+            "invite_code"
         )
-        VALUES ($1, $2, $3, $4)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
         "#,
         account_creation_verification.email,
         account_creation_verification.handle,
         account_creation_verification.verification_code,
-        account_creation_verification.verification_code_created_at
+        account_creation_verification.verification_code_created_at,
+        account_creation_verification.invite_code
     );
 
-    query.execute(&mut *transaction).await?;
+    // A CONCURRENT SIGNUP TOOK THE EMAIL OR THE HANDLE IN THE MEANTIME
 
-    // REPLACE VERIFICATION CODE PLACEHOLDER IN EMAIL BODY
-    // WITH THE ACTUAL VERIFICATION CODE
+    if query.execute(&mut *transaction).await?.rows_affected() != 1 {
+        transaction.rollback().await?;
+        let response = Response::new(StatusCode::Conflict);
+        return Ok(response);
+    }
 
-    let verification_code_placeholder = string_to_email_placeholder("verification_code");
-    let handle_placeholder = string_to_email_placeholder("handle");
+    // FILL THE EMAIL TEMPLATE WITH THE ACTUAL VERIFICATION CODE
 
-    let body_with_placeholders_replaced = &CONFIG
-        .account_creation_verification_email_body
-        .replace(
-            &handle_placeholder,
-            &f!("@{}", &account_creation_verification.handle),
-        )
-        .replace(
-            &verification_code_placeholder,
-            &account_creation_verification.verification_code,
-        );
+    let mut context = Context::new();
+    context.insert("handle", &account_creation_verification.handle);
+    context.insert(
+        "verification_code",
+        &account_creation_verification.verification_code,
+    );
 
     // SEND VERIFICATION CODE TO EMAIL
 
-    send_email(
+    queue_templated_email(
+        &mut transaction,
         &body.email,
-        &CONFIG.account_creation_verification_email_subject,
-        body_with_placeholders_replaced,
-        CONFIG.account_creation_verification_email_html,
-    )?;
+        EmailTemplate::AccountCreationVerification,
+        body.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
 
     // FINALY COMMIT TRANSACTION
 
@@ -172,6 +258,9 @@ pub async fn finish_account_creation(mut req: tide::Request<()>) -> tide::Result
 
     body.name = body.name.trim().to_string();
     body.country_code = body.country_code.to_lowercase().trim().to_string();
+    body.preferred_language = body
+        .preferred_language
+        .map(|preferred_language| preferred_language.to_lowercase().trim().to_string());
 
     // BEGIN DATABASE TRANSACTION
 
@@ -181,25 +270,38 @@ pub async fn finish_account_creation(mut req: tide::Request<()>) -> tide::Result
 
     let query = sqlx::query!(
         r#"
-            SELECT EXISTS(
-                SELECT 1
-                FROM account_creation_verifications
-                WHERE email = $1 AND handle = $2 AND verification_code = $3
-            ) AS exists
+            SELECT invite_code, verification_code_created_at
+            FROM account_creation_verifications
+            WHERE email = $1 AND handle = $2 AND verification_code = $3
+            LIMIT 1
         "#,
         &body.email,
         &body.handle,
         &body.verification_code
     );
 
-    let result = query.fetch_one(&mut *transaction).await?;
+    let verification = match query.fetch_optional(&mut *transaction).await? {
+        Some(verification) => verification,
+        None => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
 
-    if let Some(false) = result.exists {
+    // CHECK IF VERIFICATION CODE IS EXPIRED
+
+    if verification_code_expired(
+        VerificationFlow::AccountCreation,
+        verification.verification_code_created_at,
+    ) {
         transaction.rollback().await?;
-        let response = Response::new(StatusCode::NotFound);
+        let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
     }
 
+    let invite_code = verification.invite_code;
+
     // DELETE ALL ROWS FROM account_creation_verifications TABLE
     // WHERE EMAIL OR HANDLE IS EQUAL TO THE ONES
     // USED IN THE ACCOUNT THAT IS GOING TO BE CREATED
@@ -237,7 +339,7 @@ pub async fn finish_account_creation(mut req: tide::Request<()>) -> tide::Result
         name: body.name,
         email: body.email.to_owned(),
         password: encrypted_password,
-        group: Group::Default,
+        group: CONFIG.default_group.to_owned(),
         gender: body.gender,
         email_is_public: body.email_is_public,
         gender_is_public: body.gender_is_public,
@@ -248,10 +350,9 @@ pub async fn finish_account_creation(mut req: tide::Request<()>) -> tide::Result
         email_verification_codes_created_at: None,
         new_password_verification_code: None,
         new_password_verification_code_created_at: None,
+        preferred_language: body.preferred_language,
     };
 
-    let mut transaction = DATABASE_POOL.begin().await?;
-
     let query = sqlx::query!(
         r#"
         INSERT INTO accounts (
@@ -265,21 +366,23 @@ pub async fn finish_account_creation(mut req: tide::Request<()>) -> tide::Result
             "email_is_public",
             "gender_is_public",
             "country_code",
-            "created_at"
+            "created_at",
+            "preferred_language"
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
         "#,
         account.id.to_string(),
         account.handle,
         account.name,
         account.email,
         account.password,
-        account.group.to_string(),
+        account.group,
         account.gender.to_string(),
         account.email_is_public,
         account.gender_is_public,
         account.country_code,
         account.created_at,
+        account.preferred_language,
     );
 
     let result = query.execute(&mut *transaction).await?;
@@ -290,8 +393,138 @@ pub async fn finish_account_creation(mut req: tide::Request<()>) -> tide::Result
         return Ok(response);
     }
 
+    // REDEEM THE INVITE, IT MIGHT HAVE BEEN USED UP OR EXPIRED SINCE THE CODE WAS SENT
+
+    if let Some(invite_code) = invite_code {
+        let query = sqlx::query!(
+            r#"
+            UPDATE invites
+            SET uses = uses + 1
+            WHERE code = $1
+                AND (max_uses IS NULL OR uses < max_uses)
+                AND (expires_at IS NULL OR expires_at > $2)
+            "#,
+            &invite_code,
+            account.created_at
+        );
+
+        let result = query.execute(&mut *transaction).await?;
+
+        if result.rows_affected() != 1 {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::Forbidden);
+            return Ok(response);
+        }
+
+        let query = sqlx::query!(
+            r#"
+            INSERT INTO invite_redemptions (account_id, invite_code, redeemed_at)
+            VALUES ($1, $2, $3)
+            "#,
+            account.id,
+            invite_code,
+            account.created_at
+        );
+
+        query.execute(&mut *transaction).await?;
+    }
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
     Ok(Response::new(StatusCode::Ok))
 }
+
+pub async fn resend_account_creation(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: ResendAccountCreationRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE NEWEST PENDING VERIFICATION OF THE EMAIL
+
+    let query = sqlx::query!(
+        r#"
+            SELECT handle, verification_code, verification_code_created_at
+            FROM account_creation_verifications
+            WHERE email = $1
+            ORDER BY verification_code_created_at DESC
+            LIMIT 1
+            FOR UPDATE
+        "#,
+        body.email
+    );
+
+    // AN EXPIRED CODE ISN'T RESENT, THE SIGNUP HAS TO BE STARTED AGAIN
+
+    let verification = match query.fetch_optional(&mut *transaction).await? {
+        Some(verification)
+            if !verification_code_expired(
+                VerificationFlow::AccountCreation,
+                verification.verification_code_created_at,
+            ) =>
+        {
+            verification
+        }
+        _ => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // CHECK THE COOLDOWN OF THE EMAIL
+
+    if let Some(cooldown_seconds) = start_resend_cooldown(
+        &mut transaction,
+        VerificationFlow::AccountCreation,
+        &body.email,
+        verification.verification_code_created_at,
+    )
+    .await?
+    {
+        let response = Response::builder(StatusCode::TooManyRequests)
+            .body(json!(ResendVerificationResponse { cooldown_seconds }))
+            .build();
+        return Ok(response);
+    }
+
+    // FILL THE EMAIL TEMPLATE WITH THE SAME VERIFICATION CODE
+
+    let mut context = Context::new();
+    context.insert("handle", &verification.handle);
+    context.insert("verification_code", &verification.verification_code);
+
+    // SEND VERIFICATION CODE TO EMAIL
+
+    queue_templated_email(
+        &mut transaction,
+        &body.email,
+        EmailTemplate::AccountCreationVerification,
+        body.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE, WITH THE TIME UNTIL THE NEXT RESEND IS ALLOWED
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(ResendVerificationResponse {
+            cooldown_seconds: CONFIG.verification_resend_cooldown_seconds,
+        }))
+        .build();
+
+    Ok(response)
+}
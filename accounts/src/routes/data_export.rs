@@ -0,0 +1,448 @@
+use crate::{
+    config::CONFIG,
+    database::DATABASE_POOL,
+    email::queue_templated_email,
+    error::{ArchiveError, DatabaseError, Error},
+    get_decode_verify_and_return_session_token,
+    models::{
+        DataExport, DataExportAccount, DataExportDownloadToken, DataExportStatus, DeviceType,
+        EmailTemplate, Session,
+    },
+    prelude::*,
+    random::get_random_string,
+    routes::{login_events::get_login_events_of_account, picture::get_picture_from_bucket},
+    token,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::{
+    io::{Cursor, Write},
+    str::FromStr,
+};
+use tera::Context;
+use tide::{convert::json, http::mime, Response, StatusCode};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+pub async fn request_data_export(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // IMPERSONATION SESSIONS CAN'T EXPORT THE ACCOUNT DATA
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
+    // GET ACCOUNT ID FROM TOKEN
+
+    let account_id = session_token.session.account_id;
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // INSERT THE PENDING EXPORT, ONLY ONE EXPORT CAN BE
+    // PENDING AT A TIME FOR EACH ACCOUNT
+
+    let data_export = DataExport {
+        id: get_random_string(CONFIG.data_export_id_length),
+        status: DataExportStatus::Pending,
+        created_at: Utc::now().naive_utc(),
+        expires_at: Utc::now().naive_utc() + Duration::hours(CONFIG.data_export_expiry_hours),
+    };
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO data_exports (id, account_id, status, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+        "#,
+        data_export.id,
+        account_id,
+        data_export.status.to_string(),
+        data_export.created_at,
+        data_export.expires_at
+    );
+
+    let result = query.execute(&mut *transaction).await?;
+
+    if result.rows_affected() != 1 {
+        transaction.rollback().await?;
+        let response = Response::new(StatusCode::Conflict);
+        return Ok(response);
+    }
+
+    // FINALY COMMIT TRANSACTION, THE ARCHIVE IS BUILT IN THE
+    // BACKGROUND AND THE OWNER GETS AN EMAIL WHEN IT'S READY
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(data_export))
+        .build();
+
+    Ok(response)
+}
+
+pub async fn download_data_export(req: tide::Request<()>) -> tide::Result {
+    // DECODE THE DOWNLOAD TOKEN SENT BY EMAIL
+
+    let download_token: DataExportDownloadToken = match token::decode_token(req.param("token")?) {
+        Ok(download_token) => download_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // GET THE ARCHIVE IF IT'S READY AND HASN'T EXPIRED
+
+    let query = sqlx::query!(
+        r#"
+            SELECT archive
+            FROM data_exports
+            WHERE id = $1
+                AND account_id = $2
+                AND status = $3
+                AND expires_at > $4
+        "#,
+        download_token.export_id,
+        download_token.account_id,
+        DataExportStatus::Ready.to_string(),
+        Utc::now().naive_utc()
+    );
+
+    let archive = match query.fetch_optional(&*DATABASE_POOL).await? {
+        Some(result) => result.archive.unwrap_or_default(),
+        None => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // SEND THE ARCHIVE AS A DOWNLOAD
+
+    let response = Response::builder(StatusCode::Ok)
+        .content_type(mime::Mime::from_str("application/zip")?)
+        .header(
+            "Content-Disposition",
+            f!(
+                "attachment; filename=\"data-export-{}.zip\"",
+                download_token.export_id
+            ),
+        )
+        .body(archive)
+        .build();
+
+    Ok(response)
+}
+
+// A claimed export that isn't built after this long was left behind by a
+// restart or a crash, it's marked as failed so the owner can ask again
+const DATA_EXPORT_LEASE_SECONDS: i64 = 900;
+
+// Archives are built one after the other, a single poll takes at most this many
+const DATA_EXPORT_BATCH_SIZE: i64 = 5;
+
+async fn fail_stale_data_exports() -> Result<()> {
+    let query = sqlx::query!(
+        r#"
+            UPDATE data_exports
+            SET status = $1
+            WHERE status = $2 AND claimed_at <= $3
+        "#,
+        DataExportStatus::Failed.to_string(),
+        DataExportStatus::Pending.to_string(),
+        Utc::now().naive_utc() - Duration::seconds(DATA_EXPORT_LEASE_SECONDS)
+    );
+
+    let result = query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    if result.rows_affected() > 0 {
+        log::warn!(
+            "Marked {} stale data exports as failed",
+            result.rows_affected()
+        );
+    }
+
+    Ok(())
+}
+
+async fn claim_pending_data_exports() -> Result<Vec<(String, String)>> {
+    // SKIP LOCKED LETS SEVERAL WORKERS CLAIM DIFFERENT EXPORTS AT ONCE
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE data_exports
+            SET claimed_at = $1
+            WHERE id IN (
+                SELECT id
+                FROM data_exports
+                WHERE status = $2 AND claimed_at IS NULL
+                ORDER BY created_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, account_id
+        "#,
+        Utc::now().naive_utc(),
+        DataExportStatus::Pending.to_string(),
+        DATA_EXPORT_BATCH_SIZE
+    );
+
+    let exports = query
+        .fetch_all(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchAll(err.to_string())))?
+        .into_iter()
+        .map(|export| (export.id, export.account_id))
+        .collect();
+
+    Ok(exports)
+}
+
+pub async fn build_pending_data_exports() -> Result<()> {
+    fail_stale_data_exports().await?;
+
+    for (export_id, account_id) in claim_pending_data_exports().await? {
+        build_data_export(export_id, account_id).await;
+    }
+
+    Ok(())
+}
+
+async fn build_data_export(export_id: String, account_id: String) {
+    match assemble_data_export(&account_id).await {
+        Ok((account, archive)) => {
+            // STORE THE ARCHIVE BEFORE SENDING THE LINK SO IT WORKS RIGHT AWAY
+
+            let expires_at =
+                match store_data_export(&export_id, DataExportStatus::Ready, Some(archive)).await {
+                    Ok(expires_at) => expires_at,
+                    Err(err) => {
+                        log::error!("Failed to store data export {}: {}", export_id, err);
+                        return;
+                    }
+                };
+
+            if let Err(err) = send_data_export_ready_email(&export_id, &account, &expires_at).await
+            {
+                log::error!("Failed to queue data export ready email: {}", err);
+            }
+        }
+        Err(err) => {
+            log::error!("Failed to build data export {}: {}", export_id, err);
+
+            if let Err(err) = store_data_export(&export_id, DataExportStatus::Failed, None).await {
+                log::error!("Failed to store data export {}: {}", export_id, err);
+            }
+        }
+    }
+}
+
+async fn store_data_export(
+    export_id: &str,
+    status: DataExportStatus,
+    archive: Option<Vec<u8>>,
+) -> Result<NaiveDateTime> {
+    let query = sqlx::query!(
+        r#"
+            UPDATE data_exports
+            SET status = $2, archive = $3
+            WHERE id = $1
+            RETURNING expires_at
+        "#,
+        export_id,
+        status.to_string(),
+        archive
+    );
+
+    let result = query
+        .fetch_one(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    Ok(result.expires_at)
+}
+
+async fn assemble_data_export(account_id: &str) -> Result<(DataExportAccount, Vec<u8>)> {
+    // GET THE ACCOUNT, LEAVING OUT THE PASSWORD AND VERIFICATION CODES
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                id,
+                handle,
+                name,
+                email,
+                "group",
+                gender,
+                country_code,
+                email_is_public,
+                gender_is_public,
+                new_device_sign_in_email,
+                preferred_language,
+                created_at
+            FROM accounts
+            WHERE id = $1
+        "#,
+        account_id
+    );
+
+    let result = query
+        .fetch_one(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    let account = DataExportAccount {
+        id: result.id,
+        handle: result.handle,
+        name: result.name,
+        email: result.email,
+        group: result.group,
+        gender: result.gender,
+        country_code: result.country_code,
+        email_is_public: result.email_is_public,
+        gender_is_public: result.gender_is_public,
+        new_device_sign_in_email: result.new_device_sign_in_email,
+        preferred_language: result.preferred_language,
+        created_at: result.created_at,
+    };
+
+    // GET EVERY SESSION
+
+    let query = sqlx::query!(
+        r#"
+            SELECT *
+            FROM sessions
+            WHERE account_id = $1
+            ORDER BY created_at
+        "#,
+        account_id
+    );
+
+    let sessions: Vec<Session> = query
+        .fetch_all(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?
+        .into_iter()
+        .map(|session| Session {
+            id: session.id,
+            account_id: session.account_id,
+            device_name: session.device_name,
+            device_description: session.device_description,
+            device_type: DeviceType::from_str(&session.device_type).unwrap_or(DeviceType::Other),
+            ip_address: session.ip_address,
+            user_agent: session.user_agent,
+            country_code: session.country_code,
+            city: session.city,
+            impersonator_id: session.impersonator_id,
+            expire_date: session.expire_date,
+            created_at: session.created_at,
+        })
+        .collect();
+
+    // GET THE WHOLE LOGIN HISTORY
+
+    let login_events = get_login_events_of_account(account_id, 0, i64::MAX).await?;
+
+    // GET THE PROFILE PICTURE, IF THERE IS ONE
+
+    let picture = get_picture_from_bucket(&f!("{}.webp", account_id)).await?;
+
+    // WRITE EVERYTHING INTO A ZIP ARCHIVE
+
+    let mut files = vec![
+        ("account.json", to_pretty_json(&account)?),
+        ("sessions.json", to_pretty_json(&sessions)?),
+        ("login_events.json", to_pretty_json(&login_events)?),
+    ];
+
+    if let Some(picture) = picture {
+        files.push(("picture.webp", picture));
+    }
+
+    let archive = write_zip_archive(&files)?;
+
+    Ok((account, archive))
+}
+
+fn to_pretty_json<T: serde::Serialize>(value: &T) -> Result<Vec<u8>> {
+    serde_json::to_vec_pretty(value)
+        .map_err(|err| Error::Archive(ArchiveError::Write(err.to_string())))
+}
+
+fn write_zip_archive(files: &[(&str, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, contents) in files {
+        zip.start_file(*name, options)
+            .map_err(|err| Error::Archive(ArchiveError::Write(err.to_string())))?;
+        zip.write_all(contents)
+            .map_err(|err| Error::Archive(ArchiveError::Write(err.to_string())))?;
+    }
+
+    let cursor = zip
+        .finish()
+        .map_err(|err| Error::Archive(ArchiveError::Write(err.to_string())))?;
+
+    Ok(cursor.into_inner())
+}
+
+async fn send_data_export_ready_email(
+    export_id: &str,
+    account: &DataExportAccount,
+    expires_at: &NaiveDateTime,
+) -> Result<()> {
+    // THE LINK EXPIRES TOGETHER WITH THE EXPORT
+
+    let download_token = token::create_token(&DataExportDownloadToken {
+        export_id: export_id.to_owned(),
+        account_id: account.id.to_owned(),
+        exp: expires_at.and_utc().timestamp() as usize,
+    })?;
+
+    // FILL THE EMAIL TEMPLATE WITH THE DOWNLOAD DETAILS
+
+    let mut context = Context::new();
+    context.insert("handle", &account.handle);
+    context.insert(
+        "download_link",
+        &f!("{}{}", CONFIG.data_export_download_link, download_token),
+    );
+    context.insert(
+        "expires_at",
+        &expires_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    );
+
+    // QUEUE EMAIL
+
+    let mut connection = DATABASE_POOL
+        .acquire()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    queue_templated_email(
+        &mut connection,
+        &account.email,
+        EmailTemplate::DataExportReady,
+        account.preferred_language.as_deref(),
+        &context,
+    )
+    .await
+}
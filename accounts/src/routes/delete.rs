@@ -1,15 +1,25 @@
-use tide::{Response, StatusCode};
+use tide::{convert::json, Response, StatusCode};
 use validator::Validate;
 
 use crate::{
+    account_has_permission,
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    client_ip::get_client_ip_address,
     config::CONFIG,
     database::DATABASE_POOL,
-    email::send_email,
-    get_decode_verify_and_return_session_token, is_account_admin_from_id,
-    models::{AdminAccountDeletionRequest, FinishAccountDeletionRequest},
-    random::get_random_numbers,
-    string_to_email_placeholder,
+    email::queue_templated_email,
+    get_decode_verify_and_return_session_token,
+    models::{
+        AdminAccountDeletionRequest, AuditAction, EmailTemplate, FinishAccountDeletionRequest,
+        PendingAccountDeletion, Permission, ResendVerificationResponse, VerificationFlow,
+    },
+    notification::queue_security_notification,
+    prelude::*,
+    resend::start_resend_cooldown,
+    routes::picture::delete_picture_from_bucket,
+    verification::{generate_verification_code, verification_code_expired},
 };
+use tera::Context;
 
 pub async fn admin_account_deletion(mut req: tide::Request<()>) -> tide::Result {
     // GET REQUEST BODY AND VALIDATE IT
@@ -43,11 +53,11 @@ pub async fn admin_account_deletion(mut req: tide::Request<()>) -> tide::Result
 
     let account_id = session.account_id;
 
-    // CHECK IF USER IS ADMIN
+    // CHECK IF USER HAS PERMISSION TO DELETE ACCOUNTS
 
-    match is_account_admin_from_id(&account_id).await {
-        Ok(is_admin) => {
-            if !is_admin {
+    match account_has_permission(&account_id, Permission::AccountsDelete).await {
+        Ok(has_permission) => {
+            if !has_permission {
                 let response = Response::new(StatusCode::Unauthorized);
                 return Ok(response);
             }
@@ -58,28 +68,82 @@ pub async fn admin_account_deletion(mut req: tide::Request<()>) -> tide::Result
         }
     }
 
-    // DELETE THE ACCOUNT
+    // CHECK THE ACCOUNT EXISTS AND LET THE OWNER KNOW
+    // BEFORE THEIR ADDRESS IS GONE
 
     let query = sqlx::query!(
         r#"
-            DELETE FROM accounts
-            WHERE id = $1;
+            SELECT EXISTS(
+                SELECT 1 FROM accounts WHERE id = $1
+            ) AS "account_exists!"
         "#,
         body.account_id
     );
 
-    let result = query.execute(&mut *transaction).await?;
-
-    if result.rows_affected() != 1 {
+    if !query.fetch_one(&mut *transaction).await?.account_exists {
         transaction.rollback().await?;
         let response = Response::new(StatusCode::NotFound);
         return Ok(response);
     }
 
+    queue_security_notification(
+        &mut transaction,
+        &body.account_id,
+        EmailTemplate::AccountDeleted,
+        Context::new(),
+    )
+    .await?;
+
+    // DELETE THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM accounts
+            WHERE id = $1
+            RETURNING handle, email;
+        "#,
+        body.account_id
+    );
+
+    let deleted_account = match query.fetch_optional(&mut *transaction).await? {
+        Some(deleted_account) => deleted_account,
+        None => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // RECORD THE DELETION IN THE AUDIT LOG
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: account_id,
+            target_id: body.account_id.to_owned(),
+            action: AuditAction::AccountDeletion,
+            diff: json!({
+                "before": {
+                    "handle": deleted_account.handle,
+                    "email": deleted_account.email,
+                },
+                "after": null,
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
 
+    // REMOVE THE PICTURE, THE ACCOUNT IS ALREADY GONE SO ONLY LOG FAILURES
+
+    if let Err(err) = delete_picture_from_bucket(&f!("{}.webp", body.account_id)).await {
+        log::error!("Failed to delete picture of {}: {}", body.account_id, err);
+    }
+
     // SEND RESPONSE
 
     Ok(Response::new(StatusCode::Ok))
@@ -101,6 +165,13 @@ pub async fn begin_account_deletion(req: tide::Request<()>) -> tide::Result {
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T DELETE THE ACCOUNT
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET ACCOUNT ID FROM TOKEN
@@ -109,7 +180,7 @@ pub async fn begin_account_deletion(req: tide::Request<()>) -> tide::Result {
 
     // GENERATE VERIFICATION CODE
 
-    let verification_code = get_random_numbers(CONFIG.verification_code_length);
+    let verification_code = generate_verification_code(VerificationFlow::AccountDeletion);
 
     // GET TIMESTAMP
 
@@ -142,7 +213,7 @@ pub async fn begin_account_deletion(req: tide::Request<()>) -> tide::Result {
 
     let query = sqlx::query!(
         r#"
-            SELECT handle, email
+            SELECT handle, email, preferred_language
             FROM accounts 
             WHERE id = $1;
         "#,
@@ -151,28 +222,22 @@ pub async fn begin_account_deletion(req: tide::Request<()>) -> tide::Result {
 
     let result = query.fetch_one(&mut *transaction).await?;
 
-    let handle = result.handle;
-    let email = result.email;
-
-    // REPLACE VERIFICATION CODE PLACEHOLDER IN EMAIL BODY
-    // WITH THE ACTUAL VERIFICATION CODE
+    // FILL THE EMAIL TEMPLATE WITH THE ACTUAL VERIFICATION CODE
 
-    let verification_code_placeholder = string_to_email_placeholder("verification_code");
-    let handle_placeholder = string_to_email_placeholder("handle");
-
-    let body_with_placeholders_replaced = CONFIG
-        .account_deletion_verification_body
-        .replace(&verification_code_placeholder, &verification_code)
-        .replace(&handle_placeholder, &handle);
+    let mut context = Context::new();
+    context.insert("handle", &result.handle);
+    context.insert("verification_code", &verification_code);
 
     // SEND EMAIL
 
-    send_email(
-        &email,
-        &CONFIG.account_deletion_verification_subject,
-        &body_with_placeholders_replaced,
-        CONFIG.account_deletion_verification_html,
-    )?;
+    queue_templated_email(
+        &mut transaction,
+        &result.email,
+        EmailTemplate::AccountDeletionVerification,
+        result.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
 
     // FINALY COMMIT TRANSACTION
 
@@ -209,6 +274,13 @@ pub async fn finish_account_deletion(mut req: tide::Request<()>) -> tide::Result
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T DELETE THE ACCOUNT
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET ACCOUNT ID FROM TOKEN
@@ -244,11 +316,12 @@ pub async fn finish_account_deletion(mut req: tide::Request<()>) -> tide::Result
     let verification_code = verification_code.unwrap();
     let verification_code_created_at = verification_code_created_at.unwrap();
 
-    let timestamp = chrono::Utc::now().naive_utc();
+    // CHECK IF VERIFICATION CODE IS EXPIRED
 
-    let timestamp_difference = timestamp - verification_code_created_at;
-
-    if timestamp_difference < chrono::Duration::seconds(0) {
+    if verification_code_expired(
+        VerificationFlow::AccountDeletion,
+        verification_code_created_at,
+    ) {
         let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
     }
@@ -260,14 +333,24 @@ pub async fn finish_account_deletion(mut req: tide::Request<()>) -> tide::Result
         return Ok(response);
     }
 
-    // DELETE ACCOUNT
+    // SCHEDULE THE ACCOUNT FOR DELETION AFTER THE GRACE PERIOD,
+    // THE PURGE TASK REMOVES IT FOR GOOD ONCE IT'S OVER
+
+    let timestamp = chrono::Utc::now().naive_utc();
+    let deletion_scheduled_for =
+        timestamp + chrono::Duration::days(CONFIG.account_deletion_grace_period_days);
 
     let query = sqlx::query!(
         r#"
-            DELETE FROM accounts
+            UPDATE accounts
+            SET
+                deletion_scheduled_for = $2,
+                account_deletion_verification_code = NULL,
+                account_deletion_verification_code_created_at = NULL
             WHERE id = $1;
         "#,
-        account_id
+        account_id,
+        deletion_scheduled_for
     );
 
     let result = query.execute(&mut *transaction).await?;
@@ -278,11 +361,195 @@ pub async fn finish_account_deletion(mut req: tide::Request<()>) -> tide::Result
         return Ok(response);
     }
 
+    // LET THE OWNER KNOW WHEN THE ACCOUNT GOES AWAY
+
+    let mut context = Context::new();
+    context.insert(
+        "deletion_scheduled_for",
+        &deletion_scheduled_for
+            .format("%Y-%m-%d %H:%M:%S UTC")
+            .to_string(),
+    );
+
+    queue_security_notification(
+        &mut transaction,
+        &account_id,
+        EmailTemplate::AccountDeletionScheduled,
+        context,
+    )
+    .await?;
+
     // FINALY COMMIT TRANSACTION
 
     transaction.commit().await?;
 
     // SEND RESPONSE
 
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(PendingAccountDeletion {
+            deletion_scheduled_for
+        }))
+        .build();
+
+    Ok(response)
+}
+
+pub async fn cancel_account_deletion(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // IMPERSONATION SESSIONS CAN'T CANCEL THE DELETION
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
+    // GET ACCOUNT ID FROM TOKEN
+
+    let account_id = session_token.session.account_id;
+
+    // CANCEL THE SCHEDULED DELETION, IF THERE IS ONE
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE accounts
+            SET deletion_scheduled_for = NULL
+            WHERE id = $1 AND deletion_scheduled_for IS NOT NULL;
+        "#,
+        account_id
+    );
+
+    let result = query.execute(&*DATABASE_POOL).await?;
+
+    if result.rows_affected() != 1 {
+        let response = Response::new(StatusCode::NotFound);
+        return Ok(response);
+    }
+
+    // SEND RESPONSE
+
     Ok(Response::new(StatusCode::Ok))
 }
+
+pub async fn resend_account_deletion(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // IMPERSONATION SESSIONS CAN'T DELETE THE ACCOUNT
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
+    let session = session_token.session;
+
+    // GET ACCOUNT ID FROM TOKEN
+
+    let account_id = session.account_id;
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE PENDING VERIFICATION CODE OF THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                handle,
+                email,
+                preferred_language,
+                account_deletion_verification_code,
+                account_deletion_verification_code_created_at
+            FROM accounts
+            WHERE id = $1
+            FOR UPDATE
+        "#,
+        account_id
+    );
+
+    let account = query.fetch_one(&mut *transaction).await?;
+
+    let (verification_code, verification_code_created_at) = match (
+        account.account_deletion_verification_code,
+        account.account_deletion_verification_code_created_at,
+    ) {
+        (Some(verification_code), Some(verification_code_created_at))
+            if !verification_code_expired(
+                VerificationFlow::AccountDeletion,
+                verification_code_created_at,
+            ) =>
+        {
+            (verification_code, verification_code_created_at)
+        }
+        _ => {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // CHECK THE COOLDOWN OF THE ACCOUNT
+
+    if let Some(cooldown_seconds) = start_resend_cooldown(
+        &mut transaction,
+        VerificationFlow::AccountDeletion,
+        &account_id,
+        verification_code_created_at,
+    )
+    .await?
+    {
+        let response = Response::builder(StatusCode::TooManyRequests)
+            .body(json!(ResendVerificationResponse { cooldown_seconds }))
+            .build();
+        return Ok(response);
+    }
+
+    // FILL THE EMAIL TEMPLATE WITH THE SAME VERIFICATION CODE
+
+    let mut context = Context::new();
+    context.insert("handle", &account.handle);
+    context.insert("verification_code", &verification_code);
+
+    // SEND EMAIL
+
+    queue_templated_email(
+        &mut transaction,
+        &account.email,
+        EmailTemplate::AccountDeletionVerification,
+        account.preferred_language.as_deref(),
+        &context,
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE, WITH THE TIME UNTIL THE NEXT RESEND IS ALLOWED
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(ResendVerificationResponse {
+            cooldown_seconds: CONFIG.verification_resend_cooldown_seconds,
+        }))
+        .build();
+
+    Ok(response)
+}
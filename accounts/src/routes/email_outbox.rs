@@ -0,0 +1,164 @@
+use crate::{
+    account_has_permission,
+    database::DATABASE_POOL,
+    get_decode_verify_and_return_session_token,
+    models::{
+        EmailOutboxEntry, EmailOutboxList, EmailOutboxStatus, GetEmailOutboxRequest, Permission,
+        RetryEmailOutboxEntryRequest,
+    },
+};
+use chrono::Utc;
+use tide::{convert::json, Response, StatusCode};
+use validator::Validate;
+
+pub async fn admin_get_email_outbox(req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST INFO FROM QUERY PARAMS AND VALIDATE IT
+
+    let info: GetEmailOutboxRequest = req.query()?;
+
+    if info.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(info.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO MANAGE THE EMAIL OUTBOX
+
+    match account_has_permission(&account_id, Permission::EmailOutboxManage).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // GET THE EMAILS MATCHING THE FILTERS, NEWEST FIRST,
+    // THE BODIES ARE LEFT OUT SINCE THEY CARRY VERIFICATION CODES
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                id,
+                recipient,
+                subject,
+                status,
+                attempts,
+                last_error,
+                next_attempt_at,
+                created_at,
+                sent_at
+            FROM email_outbox
+            WHERE ($1::TEXT IS NULL OR status = $1)
+                AND ($2::TEXT IS NULL OR recipient = $2)
+            ORDER BY id DESC
+            OFFSET $3
+            LIMIT $4
+        "#,
+        info.status.map(|status| status.to_string()),
+        info.recipient,
+        info.start,
+        info.ammount
+    );
+
+    let entries = query
+        .fetch_all(&*DATABASE_POOL)
+        .await?
+        .into_iter()
+        .map(|entry| EmailOutboxEntry {
+            id: entry.id,
+            recipient: entry.recipient,
+            subject: entry.subject,
+            status: entry.status,
+            attempts: entry.attempts,
+            last_error: entry.last_error,
+            next_attempt_at: entry.next_attempt_at,
+            created_at: entry.created_at,
+            sent_at: entry.sent_at,
+        })
+        .collect();
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(EmailOutboxList { entries }))
+        .build();
+
+    Ok(response)
+}
+
+pub async fn admin_retry_email_outbox_entry(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY
+
+    let body: RetryEmailOutboxEntryRequest = req.body_json().await?;
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO MANAGE THE EMAIL OUTBOX
+
+    match account_has_permission(&account_id, Permission::EmailOutboxManage).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // PUT THE DEAD EMAIL BACK IN THE QUEUE WITH A FRESH SET OF ATTEMPTS
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE email_outbox
+            SET status = $1, attempts = 0, next_attempt_at = $2
+            WHERE id = $3 AND status = $4
+        "#,
+        EmailOutboxStatus::Pending.to_string(),
+        Utc::now().naive_utc(),
+        body.id,
+        EmailOutboxStatus::Dead.to_string()
+    );
+
+    let result = query.execute(&*DATABASE_POOL).await?;
+
+    if result.rows_affected() != 1 {
+        let response = Response::new(StatusCode::NotFound);
+        return Ok(response);
+    }
+
+    // SEND RESPONSE
+
+    Ok(Response::new(StatusCode::Ok))
+}
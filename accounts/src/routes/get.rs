@@ -1,11 +1,14 @@
 use crate::{
+    account_has_permission,
     database::DATABASE_POOL,
     error::{DatabaseError, Error},
-    get_decode_verify_and_return_session_token, get_id_from_handle, is_account_admin_from_id,
+    get_account_permissions, get_decode_verify_and_return_session_token, get_id_from_handle,
     models::{
-        AccountInfoToGet, AccountPublic, Gender, GetAccountRequest, GetAllAccountsAccount,
-        GetAllAccountsResponse, Group,
+        AccountInfoToGet, AccountPublic, AccountSortColumn, Gender, GetAccountRequest,
+        GetAccountsPageRequest, GetAccountsPageResponse, GetAllAccountsAccount,
+        GetAllAccountsResponse, Permission, PermissionList, SortOrder,
     },
+    prelude::*,
 };
 use std::str::FromStr;
 use tide::{convert::json, Response, StatusCode};
@@ -25,12 +28,11 @@ pub async fn get_is_admin(req: tide::Request<()>) -> tide::Result {
 
     let account_id = session_token.session.account_id;
 
-    let is_admin = match is_account_admin_from_id(&account_id).await {
+    // ONLY ACCOUNTS THAT CAN ASSIGN GROUPS ARE ADMINS, LIKE THE OLD ADMIN GROUP,
+    // CLIENTS LOOKING FOR FINER GRAINED ACCESS SHOULD USE /admin/permissions
+
+    let is_admin = match account_has_permission(&account_id, Permission::GroupsAssign).await {
         Ok(is_admin) => is_admin,
-        Err(Error::Database(DatabaseError::RowNotFound)) => {
-            let response = Response::new(StatusCode::NotFound);
-            return Ok(response);
-        }
         Err(_) => {
             let response = Response::new(StatusCode::InternalServerError);
             return Ok(response);
@@ -43,6 +45,39 @@ pub async fn get_is_admin(req: tide::Request<()>) -> tide::Result {
     }
 }
 
+pub async fn get_permissions(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // GET THE PERMISSIONS GRANTED BY THE ACCOUNT GROUP
+
+    let permissions = match get_account_permissions(&account_id).await {
+        Ok(permissions) => permissions,
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    };
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(PermissionList { permissions }))
+        .build();
+
+    Ok(response)
+}
+
 pub async fn get_account(req: tide::Request<()>) -> tide::Result {
     // GET REQUEST INFO FROM QUERY PARAMS AND VALIDATE IT
 
@@ -69,9 +104,12 @@ pub async fn get_account(req: tide::Request<()>) -> tide::Result {
     ) {
         (Some(session_token), Some(id), _) => {
             let session = session_token.session;
-            let is_admin = is_account_admin_from_id(&session.account_id).await.unwrap_or(false);
+            let can_read_accounts =
+                account_has_permission(&session.account_id, Permission::AccountsRead)
+                    .await
+                    .unwrap_or(false);
 
-            ((session.account_id == id || is_admin), id)
+            ((session.account_id == id || can_read_accounts), id)
         }
         (Some(session_token), _, Some(handle)) => {
             let session = session_token.session;
@@ -90,9 +128,12 @@ pub async fn get_account(req: tide::Request<()>) -> tide::Result {
                 }
             };
 
-            let is_admin = is_account_admin_from_id(&session.account_id).await.unwrap_or(false);
+            let can_read_accounts =
+                account_has_permission(&session.account_id, Permission::AccountsRead)
+                    .await
+                    .unwrap_or(false);
 
-            ((session.account_id == id || is_admin), id)
+            ((session.account_id == id || can_read_accounts), id)
         }
         (Some(session_token), _, _) => {
             let session = session_token.session;
@@ -139,6 +180,7 @@ pub async fn get_account(req: tide::Request<()>) -> tide::Result {
             gender_is_public: None,
             country_code: None,
             created_at: None,
+            new_device_sign_in_email: None,
         },
     };
 
@@ -163,7 +205,8 @@ pub async fn get_account(req: tide::Request<()>) -> tide::Result {
             CASE WHEN $8 THEN email_is_public ELSE NULL END AS email_is_public,
             CASE WHEN $9 THEN gender_is_public ELSE NULL END AS gender_is_public,
             CASE WHEN $10 THEN country_code ELSE NULL END AS country_code,
-            CASE WHEN $11 THEN created_at ELSE NULL END AS created_at
+            CASE WHEN $11 THEN created_at ELSE NULL END AS created_at,
+            CASE WHEN $12 THEN new_device_sign_in_email ELSE NULL END AS new_device_sign_in_email
         FROM accounts
         WHERE id = $1;
     "#,
@@ -178,6 +221,7 @@ pub async fn get_account(req: tide::Request<()>) -> tide::Result {
         get_gender_is_public,
         info_to_get.country_code,
         info_to_get.created_at,
+        info_to_get.new_device_sign_in_email,
     );
 
     let result = query.fetch_optional(&mut *transaction).await?;
@@ -204,11 +248,6 @@ pub async fn get_account(req: tide::Request<()>) -> tide::Result {
         _ => None,
     };
 
-    let treated_group = match result.group {
-        Some(group) => Some(Group::from_str(&group)?),
-        None => None,
-    };
-
     let treated_gender = match (result.gender, result.gender_is_public) {
         (Some(gender), Some(gender_is_public)) => {
             if owner_of_account || gender_is_public {
@@ -220,17 +259,24 @@ pub async fn get_account(req: tide::Request<()>) -> tide::Result {
         _ => None,
     };
 
+    let treated_new_device_sign_in_email = if owner_of_account {
+        result.new_device_sign_in_email
+    } else {
+        None
+    };
+
     let account_info = AccountPublic {
         id: result.id,
         handle: result.handle,
         name: result.name,
         email: treated_email,
-        group: treated_group,
+        group: result.group,
         gender: treated_gender,
         email_is_public: result.email_is_public,
         gender_is_public: result.gender_is_public,
         country_code: result.country_code,
         created_at: result.created_at,
+        new_device_sign_in_email: treated_new_device_sign_in_email,
     };
 
     // FINALY COMMIT TRANSACTION
@@ -260,25 +306,28 @@ pub async fn get_all_accounts(req: tide::Request<()>) -> tide::Result {
 
     let account_id = session_token.session.account_id;
 
-    // CHECK IF ACCOUNT IS ADMIN
+    // CHECK IF ACCOUNT HAS PERMISSION TO READ ACCOUNTS
 
-    let is_admin = match is_account_admin_from_id(&account_id).await {
-        Ok(is_admin) => is_admin,
-        Err(Error::Database(DatabaseError::RowNotFound)) => {
-            let response = Response::new(StatusCode::NotFound);
-            return Ok(response);
-        }
+    let can_read_accounts = match account_has_permission(&account_id, Permission::AccountsRead).await {
+        Ok(can_read_accounts) => can_read_accounts,
         Err(_) => {
             let response = Response::new(StatusCode::InternalServerError);
             return Ok(response);
         }
     };
 
-    if !is_admin {
+    if !can_read_accounts {
         let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
     }
 
+    // WITHOUT QUERY PARAMS KEEP RESPONDING WITH EVERY ACCOUNT
+    // SO CLIENTS WRITTEN BEFORE PAGINATION KEEP WORKING
+
+    if req.url().query().is_some() {
+        return get_accounts_page(req).await;
+    }
+
     // BEGIN DATABASE TRANSACTION
 
     let mut transaction = DATABASE_POOL.begin().await?;
@@ -300,7 +349,7 @@ pub async fn get_all_accounts(req: tide::Request<()>) -> tide::Result {
             handle: result.handle.to_owned(),
             name: result.name.to_owned(),
             email: result.email.to_owned(),
-            group: Group::from_str(&result.group).unwrap_or(Group::Default),
+            group: result.group.to_owned(),
             gender: Gender::from_str(&result.gender).unwrap_or(Gender::NotSpecified),
             email_is_public: result.email_is_public,
             gender_is_public: result.gender_is_public,
@@ -315,3 +364,173 @@ pub async fn get_all_accounts(req: tide::Request<()>) -> tide::Result {
 
     Ok(response)
 }
+
+fn escape_like_pattern(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+async fn get_accounts_page(req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST INFO FROM QUERY PARAMS AND VALIDATE IT
+
+    let info: GetAccountsPageRequest = req.query()?;
+
+    if info.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(info.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    let search = info
+        .search
+        .map(|search| f!("%{}%", escape_like_pattern(search.trim())));
+    let country_code = info
+        .country_code
+        .map(|country_code| country_code.to_lowercase().trim().to_string());
+    let sort = info.sort.unwrap_or(AccountSortColumn::CreatedAt).to_string();
+    let descending = info.order == Some(SortOrder::Desc);
+    let ammount = info.ammount.unwrap_or(50);
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // A CURSOR POINTING TO NO ACCOUNT WOULD RETURN AN EMPTY PAGE THAT
+    // LOOKS LIKE THE END OF THE LIST, SO IT IS REJECTED INSTEAD
+
+    if let Some(cursor) = &info.cursor {
+        let query = sqlx::query!(
+            r#"
+                SELECT EXISTS(SELECT 1 FROM accounts WHERE id = $1) AS "cursor_exists!";
+            "#,
+            cursor
+        );
+
+        if !query.fetch_one(&mut *transaction).await?.cursor_exists {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::UnprocessableEntity);
+            return Ok(response);
+        }
+    }
+
+    // COUNT EVERY ACCOUNT MATCHING THE FILTERS
+
+    let query = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "total_count!"
+            FROM accounts
+            WHERE ($1::TEXT IS NULL OR handle ILIKE $1 OR name ILIKE $1 OR email ILIKE $1)
+                AND ($2::TEXT IS NULL OR "group" = $2)
+                AND ($3::TEXT IS NULL OR country_code = $3)
+                AND ($4::TIMESTAMP IS NULL OR created_at >= $4)
+                AND ($5::TIMESTAMP IS NULL OR created_at < $5)
+        "#,
+        search,
+        info.group,
+        country_code,
+        info.created_from,
+        info.created_to
+    );
+
+    let total_count = query.fetch_one(&mut *transaction).await?.total_count;
+
+    // GET THE PAGE AFTER THE CURSOR ACCOUNT, ORDERED BY THE SORT COLUMN
+    // AND THEN BY ID SO ACCOUNTS WITH THE SAME VALUE KEEP A STABLE ORDER.
+    // ONE EXTRA ACCOUNT IS FETCHED TO KNOW IF THERE IS A NEXT PAGE
+
+    let query = sqlx::query!(
+        r#"
+            WITH sorted_accounts AS NOT MATERIALIZED (
+                SELECT
+                    *,
+                    CASE $6
+                        WHEN 'handle' THEN handle
+                        WHEN 'name' THEN name
+                        WHEN 'email' THEN email
+                        ELSE to_char(created_at, 'YYYY-MM-DD"T"HH24:MI:SS.US')
+                    END AS sort_key
+                FROM accounts
+            )
+            SELECT
+                id,
+                handle,
+                name,
+                email,
+                "group",
+                gender,
+                email_is_public,
+                gender_is_public,
+                country_code,
+                created_at
+            FROM sorted_accounts
+            WHERE ($1::TEXT IS NULL OR handle ILIKE $1 OR name ILIKE $1 OR email ILIKE $1)
+                AND ($2::TEXT IS NULL OR "group" = $2)
+                AND ($3::TEXT IS NULL OR country_code = $3)
+                AND ($4::TIMESTAMP IS NULL OR created_at >= $4)
+                AND ($5::TIMESTAMP IS NULL OR created_at < $5)
+                AND (
+                    $8::TEXT IS NULL
+                    OR CASE WHEN $7::BOOLEAN
+                        THEN (sort_key, id) < (SELECT sort_key, id FROM sorted_accounts WHERE id = $8)
+                        ELSE (sort_key, id) > (SELECT sort_key, id FROM sorted_accounts WHERE id = $8)
+                    END
+                )
+            ORDER BY
+                CASE WHEN $7 THEN NULL ELSE sort_key END ASC,
+                CASE WHEN $7 THEN sort_key END DESC,
+                CASE WHEN $7 THEN NULL ELSE id END ASC,
+                CASE WHEN $7 THEN id END DESC
+            LIMIT $9
+        "#,
+        search,
+        info.group,
+        country_code,
+        info.created_from,
+        info.created_to,
+        sort,
+        descending,
+        info.cursor,
+        ammount + 1
+    );
+
+    let mut results = query.fetch_all(&mut *transaction).await?;
+
+    let has_next_page = results.len() as i64 > ammount;
+    results.truncate(ammount as usize);
+
+    let accounts: Vec<GetAllAccountsAccount> = results
+        .iter()
+        .map(|result| GetAllAccountsAccount {
+            id: result.id.to_owned(),
+            handle: result.handle.to_owned(),
+            name: result.name.to_owned(),
+            email: result.email.to_owned(),
+            group: result.group.to_owned(),
+            gender: Gender::from_str(&result.gender).unwrap_or(Gender::NotSpecified),
+            email_is_public: result.email_is_public,
+            gender_is_public: result.gender_is_public,
+            country_code: result.country_code.to_owned(),
+            created_at: result.created_at,
+        })
+        .collect();
+
+    // THE CURSOR OF THE NEXT PAGE IS THE ID OF THE LAST ACCOUNT ON THIS ONE
+
+    let next_cursor = if has_next_page {
+        accounts.last().map(|account| account.id.to_owned())
+    } else {
+        None
+    };
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(GetAccountsPageResponse {
+            accounts,
+            total_count,
+            next_cursor,
+        }))
+        .build();
+
+    Ok(response)
+}
@@ -0,0 +1,193 @@
+use chrono::{Duration, Utc};
+use tide::{convert::json, Response, StatusCode};
+use validator::Validate;
+
+use crate::{
+    account_has_permission,
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    client_ip::get_client_ip_address,
+    config::CONFIG,
+    database::DATABASE_POOL,
+    geolocation::locate_ip_address,
+    get_decode_verify_and_return_session_token,
+    models::{
+        AdminImpersonationRequest, AuditAction, DeviceType, Permission, SessionToken,
+        SessionTokenInfo, Token,
+    },
+    random::get_random_string,
+    token,
+};
+
+pub async fn admin_impersonation(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: AdminImpersonationRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // IMPERSONATION SESSIONS CAN'T START ANOTHER IMPERSONATION
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO IMPERSONATE ACCOUNTS
+
+    match account_has_permission(&account_id, Permission::AccountsImpersonate).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    if body.account_id == account_id {
+        let response = Response::new(StatusCode::UnprocessableEntity);
+        return Ok(response);
+    }
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // CHECK THAT THE TARGET EXISTS AND HOLDS NO PERMISSIONS,
+    // STAFF ACCOUNTS CAN'T BE IMPERSONATED
+
+    let query = sqlx::query!(
+        r#"
+            SELECT EXISTS(
+                SELECT 1
+                FROM role_permissions
+                WHERE role_permissions.role = accounts."group"
+            ) AS "has_permissions!"
+            FROM accounts
+            WHERE id = $1
+        "#,
+        &body.account_id
+    );
+
+    match query.fetch_optional(&mut *transaction).await? {
+        Some(result) => {
+            if result.has_permissions {
+                transaction.rollback().await?;
+                let response = Response::new(StatusCode::Forbidden);
+                return Ok(response);
+            }
+        }
+        None => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    }
+
+    // INSERT A SHORT LIVED SESSION FOR THE TARGET, MARKED WITH THE IMPERSONATOR
+
+    let ip_address = get_client_ip_address(&req);
+
+    let user_agent = req
+        .header("User-Agent")
+        .map(|value| value.as_str().to_string());
+
+    let geolocation = locate_ip_address(&ip_address);
+
+    let session_id = get_random_string(CONFIG.session_id_length);
+    let created_at = Utc::now().naive_utc();
+    let expire_date = created_at + Duration::minutes(CONFIG.impersonation_session_minutes);
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO sessions (id, account_id, device_name, device_description, device_type, ip_address, user_agent, country_code, city, impersonator_id, expire_date, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#,
+        session_id,
+        &body.account_id,
+        "Impersonation",
+        "",
+        DeviceType::Other.to_string(),
+        ip_address,
+        user_agent,
+        geolocation.country_code,
+        geolocation.city,
+        account_id,
+        expire_date,
+        created_at
+    );
+
+    let result = query.execute(&mut *transaction).await?;
+
+    if result.rows_affected() != 1 {
+        let response = Response::new(StatusCode::InternalServerError);
+        return Ok(response);
+    }
+
+    // RECORD THE IMPERSONATION IN THE AUDIT LOG
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: account_id.to_owned(),
+            target_id: body.account_id.to_owned(),
+            action: AuditAction::ImpersonationStart,
+            diff: json!({
+                "before": null,
+                "after": {
+                    "session_id": session_id,
+                    "expire_date": expire_date,
+                },
+            }),
+            ip_address,
+        },
+    )
+    .await?;
+
+    // CREATE TOKEN CARRYING THE IMPERSONATOR
+
+    let session_token = SessionToken {
+        session: SessionTokenInfo {
+            id: session_id,
+            account_id: body.account_id,
+            expire_date,
+            created_at,
+        },
+        impersonator_id: Some(account_id),
+        exp: expire_date.and_utc().timestamp() as usize,
+    };
+
+    let token = Token {
+        token: token::create_token(&session_token)?,
+    };
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok).body(json!(token)).build();
+
+    Ok(response)
+}
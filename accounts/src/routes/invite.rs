@@ -0,0 +1,277 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use tide::{convert::json, Response, StatusCode};
+use validator::Validate;
+
+use crate::{
+    account_has_permission,
+    config::CONFIG,
+    database::DATABASE_POOL,
+    get_decode_verify_and_return_session_token,
+    models::{CreateInviteRequest, GetInvitesResponse, Invite, InviteRedemption, Permission},
+    random::get_random_string,
+};
+
+pub async fn create_invite(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: CreateInviteRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    let created_at = Utc::now().naive_utc();
+
+    // AN INVITE THAT WOULD ALREADY BE EXPIRED MAKES NO SENSE
+
+    if body
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= created_at)
+    {
+        let response = Response::new(StatusCode::UnprocessableEntity);
+        return Ok(response);
+    }
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // IMPERSONATION SESSIONS CAN'T CREATE INVITES
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
+    let account_id = session_token.session.account_id;
+
+    // ACCOUNTS WITH PERMISSION TO CREATE INVITES AREN'T LIMITED BY THE QUOTA
+
+    let has_permission = match account_has_permission(&account_id, Permission::InvitesCreate).await
+    {
+        Ok(has_permission) => has_permission,
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    };
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // THE QUOTA COUNTS SIGNUPS, NOT INVITES, SO AN INVITE LIMITED BY IT
+    // MUST SAY HOW MANY TIMES IT CAN BE USED AND WHEN IT RUNS OUT
+
+    if !has_permission {
+        let max_uses = match (body.max_uses, body.expires_at) {
+            (Some(max_uses), Some(_)) => max_uses,
+            _ => {
+                transaction.rollback().await?;
+                let response = Response::new(StatusCode::UnprocessableEntity);
+                return Ok(response);
+            }
+        };
+
+        // CHECK THE REMAINING QUOTA, USES OF EXPIRED INVITES ONLY COUNT IF THEY
+        // WERE REDEEMED, THE ACCOUNT ROW IS LOCKED SO CONCURRENT REQUESTS CAN'T GO OVER IT
+
+        let query = sqlx::query!(
+            r#"
+                SELECT (
+                    SELECT COALESCE(SUM(
+                        CASE
+                            WHEN expires_at IS NOT NULL AND expires_at <= $2 THEN uses
+                            ELSE COALESCE(max_uses, uses)
+                        END
+                    ), 0)
+                    FROM invites
+                    WHERE created_by = accounts.id
+                ) AS "allotted_uses!"
+                FROM accounts
+                WHERE id = $1
+                FOR UPDATE
+            "#,
+            &account_id,
+            created_at
+        );
+
+        let result = query.fetch_one(&mut *transaction).await?;
+
+        if result.allotted_uses + i64::from(max_uses) > CONFIG.invite_quota_per_account {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::Forbidden);
+            return Ok(response);
+        }
+    }
+
+    // INSERT THE INVITE
+
+    let invite = Invite {
+        code: get_random_string(CONFIG.invite_code_length),
+        max_uses: body.max_uses,
+        uses: 0,
+        expires_at: body.expires_at,
+        created_at,
+        redemptions: Vec::new(),
+    };
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO invites (code, created_by, max_uses, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+        "#,
+        invite.code,
+        account_id,
+        invite.max_uses,
+        invite.expires_at,
+        invite.created_at
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(invite))
+        .build();
+
+    Ok(response)
+}
+
+pub async fn get_invites(req: tide::Request<()>) -> tide::Result {
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    let has_permission = match account_has_permission(&account_id, Permission::InvitesCreate).await
+    {
+        Ok(has_permission) => has_permission,
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    };
+
+    // GET THE INVITES CREATED BY THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            SELECT code, max_uses, uses, expires_at, created_at
+            FROM invites
+            WHERE created_by = $1
+            ORDER BY created_at DESC
+        "#,
+        &account_id
+    );
+
+    let invites = query.fetch_all(&*DATABASE_POOL).await?;
+
+    // GET WHO REDEEMED THEM
+
+    let codes: Vec<String> = invites
+        .iter()
+        .map(|invite| invite.code.to_owned())
+        .collect();
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                invite_redemptions.invite_code,
+                invite_redemptions.account_id,
+                invite_redemptions.redeemed_at,
+                accounts.handle
+            FROM invite_redemptions
+            JOIN accounts ON accounts.id = invite_redemptions.account_id
+            WHERE invite_redemptions.invite_code = ANY($1)
+            ORDER BY invite_redemptions.redeemed_at
+        "#,
+        &codes
+    );
+
+    let mut redemptions: HashMap<String, Vec<InviteRedemption>> = HashMap::new();
+
+    for redemption in query.fetch_all(&*DATABASE_POOL).await? {
+        redemptions
+            .entry(redemption.invite_code)
+            .or_default()
+            .push(InviteRedemption {
+                account_id: redemption.account_id,
+                handle: redemption.handle,
+                redeemed_at: redemption.redeemed_at,
+            });
+    }
+
+    let invites: Vec<Invite> = invites
+        .into_iter()
+        .map(|invite| Invite {
+            redemptions: redemptions.remove(&invite.code).unwrap_or_default(),
+            code: invite.code,
+            max_uses: invite.max_uses,
+            uses: invite.uses,
+            expires_at: invite.expires_at,
+            created_at: invite.created_at,
+        })
+        .collect();
+
+    // NONE MEANS THE ACCOUNT CAN CREATE INVITES WITHOUT LIMIT
+
+    let remaining_quota = if has_permission {
+        None
+    } else {
+        // COUNTED THE SAME WAY AS WHEN AN INVITE IS CREATED
+
+        let query = sqlx::query!(
+            r#"
+                SELECT COALESCE(SUM(
+                    CASE
+                        WHEN expires_at IS NOT NULL AND expires_at <= $2 THEN uses
+                        ELSE COALESCE(max_uses, uses)
+                    END
+                ), 0) AS "allotted_uses!"
+                FROM invites
+                WHERE created_by = $1
+            "#,
+            &account_id,
+            Utc::now().naive_utc()
+        );
+
+        let result = query.fetch_one(&*DATABASE_POOL).await?;
+
+        Some((CONFIG.invite_quota_per_account - result.allotted_uses).max(0))
+    };
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(GetInvitesResponse {
+            invites,
+            remaining_quota,
+        }))
+        .build();
+
+    Ok(response)
+}
@@ -0,0 +1,270 @@
+use crate::{
+    account_has_permission,
+    config::CONFIG,
+    database::DATABASE_POOL,
+    error::{DatabaseError, Error},
+    get_decode_verify_and_return_session_token,
+    models::{
+        DeviceType, GetLoginEventsRequest, LoginEvent, LoginEventList, LoginEventOutcome,
+        LoginEventReason, Permission,
+    },
+    prelude::*,
+    random::get_random_string,
+};
+use std::str::FromStr;
+use tide::{convert::json, Response, StatusCode};
+use validator::Validate;
+
+// The history is best effort, failing to write it never fails the login itself
+pub async fn record_login_event(login_event: &LoginEvent) {
+    if let Err(err) = insert_login_event(login_event).await {
+        log::error!("Failed to record login event {}: {}", login_event.id, err);
+    }
+}
+
+async fn insert_login_event(login_event: &LoginEvent) -> Result<()> {
+    // THE EVENT IS WRITTEN OUTSIDE OF ANY TRANSACTION SO THAT
+    // FAILED ATTEMPTS ARE KEPT EVEN WHEN THE REQUEST IS ROLLED BACK
+
+    let query = sqlx::query!(
+        r#"
+            INSERT INTO login_events (
+                "id",
+                "account_id",
+                "email",
+                "session_id",
+                "ip_address",
+                "user_agent",
+                "device_name",
+                "device_description",
+                "device_type",
+                "outcome",
+                "reason",
+                "created_at"
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#,
+        login_event.id,
+        login_event.account_id,
+        login_event.email,
+        login_event.session_id,
+        login_event.ip_address,
+        login_event.user_agent,
+        login_event.device_name,
+        login_event.device_description,
+        login_event.device_type.to_string(),
+        login_event.outcome.to_string(),
+        login_event.reason.to_string(),
+        login_event.created_at
+    );
+
+    query
+        .execute(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    Ok(())
+}
+
+pub async fn is_new_device_for_account(login_event: &LoginEvent) -> Result<bool> {
+    // A DEVICE IS NEW IF THE ACCOUNT ALREADY HAS SUCCESSFUL LOGINS
+    // BUT NONE OF THEM CAME FROM THIS IP ADDRESS OR FROM THIS DEVICE
+
+    let query = sqlx::query!(
+        r#"
+            SELECT
+                EXISTS(
+                    SELECT 1 FROM login_events
+                    WHERE account_id = $1 AND outcome = $2
+                ) AS has_previous_logins,
+                EXISTS(
+                    SELECT 1 FROM login_events
+                    WHERE account_id = $1 AND outcome = $2 AND ip_address = $3
+                ) AS ip_address_seen,
+                EXISTS(
+                    SELECT 1 FROM login_events
+                    WHERE account_id = $1 AND outcome = $2
+                        AND device_name = $4
+                        AND user_agent IS NOT DISTINCT FROM $5
+                ) AS device_seen
+        "#,
+        login_event.account_id,
+        LoginEventOutcome::Success.to_string(),
+        login_event.ip_address,
+        login_event.device_name,
+        login_event.user_agent
+    );
+
+    let result = query
+        .fetch_one(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchOne(err.to_string())))?;
+
+    match (
+        result.has_previous_logins,
+        result.ip_address_seen,
+        result.device_seen,
+    ) {
+        (Some(true), Some(ip_address_seen), Some(device_seen)) => {
+            Ok(!ip_address_seen || !device_seen)
+        }
+        _ => Ok(false),
+    }
+}
+
+pub fn get_new_login_event_id() -> String {
+    get_random_string(CONFIG.login_event_id_length)
+}
+
+pub async fn get_login_events_of_account(
+    account_id: &str,
+    start_index: i64,
+    ammount: i64,
+) -> Result<Vec<LoginEvent>> {
+    let query = sqlx::query!(
+        r#"
+            SELECT *
+            FROM login_events
+            WHERE account_id = $1
+            ORDER BY created_at DESC, id
+            OFFSET $2
+            LIMIT $3
+        "#,
+        account_id,
+        start_index,
+        ammount
+    );
+
+    let login_events = query
+        .fetch_all(&*DATABASE_POOL)
+        .await
+        .map_err(|err| Error::Database(DatabaseError::FetchAll(err.to_string())))?
+        .into_iter()
+        .map(|login_event| LoginEvent {
+            id: login_event.id,
+            account_id: login_event.account_id,
+            email: login_event.email,
+            session_id: login_event.session_id,
+            ip_address: login_event.ip_address,
+            user_agent: login_event.user_agent,
+            device_name: login_event.device_name,
+            device_description: login_event.device_description,
+            device_type: DeviceType::from_str(&login_event.device_type)
+                .unwrap_or(DeviceType::Other),
+            outcome: LoginEventOutcome::from_str(&login_event.outcome)
+                .unwrap_or(LoginEventOutcome::Failure),
+            reason: LoginEventReason::from_str(&login_event.reason)
+                .unwrap_or(LoginEventReason::WrongPassword),
+            created_at: login_event.created_at,
+        })
+        .collect();
+
+    Ok(login_events)
+}
+
+fn get_pagination_from_params(req: &tide::Request<()>) -> Option<(i64, i64)> {
+    let pagination = GetLoginEventsRequest {
+        start: req.param("start").ok()?.parse().ok()?,
+        ammount: req.param("ammount").ok()?.parse().ok()?,
+    };
+
+    if pagination.validate().is_err() {
+        return None;
+    }
+
+    Some((pagination.start, pagination.ammount))
+}
+
+pub async fn get_login_events(req: tide::Request<()>) -> tide::Result {
+    // GET THE STARTING INDEX AND THE AMMOUNT OF EVENTS TO GET
+
+    let (start_index, ammount) = match get_pagination_from_params(&req) {
+        Some(pagination) => pagination,
+        None => {
+            let response = Response::new(StatusCode::UnprocessableEntity);
+            return Ok(response);
+        }
+    };
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // GET LOGIN EVENTS OF THE ACCOUNT
+
+    let login_events = get_login_events_of_account(&account_id, start_index, ammount).await?;
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(LoginEventList { login_events }))
+        .build();
+
+    Ok(response)
+}
+
+pub async fn admin_get_login_events(req: tide::Request<()>) -> tide::Result {
+    // GET THE STARTING INDEX AND THE AMMOUNT OF EVENTS TO GET
+
+    let (start_index, ammount) = match get_pagination_from_params(&req) {
+        Some(pagination) => pagination,
+        None => {
+            let response = Response::new(StatusCode::UnprocessableEntity);
+            return Ok(response);
+        }
+    };
+
+    // GET THE ACCOUNT ID TO GET THE EVENTS OF
+
+    let target_account_id = req.param("account_id")?.to_string();
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO READ LOGIN EVENTS
+
+    match account_has_permission(&account_id, Permission::SessionsRead).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // GET LOGIN EVENTS OF THE TARGET ACCOUNT
+
+    let login_events =
+        get_login_events_of_account(&target_account_id, start_index, ammount).await?;
+
+    // SEND RESPONSE
+
+    let response = Response::builder(StatusCode::Ok)
+        .body(json!(LoginEventList { login_events }))
+        .build();
+
+    Ok(response)
+}
@@ -1,10 +1,18 @@
+pub mod audit_log;
+pub mod bulk;
 pub mod change_email;
 pub mod change_group;
 pub mod change_info;
 pub mod change_password;
 pub mod create;
+pub mod data_export;
 pub mod delete;
+pub mod email_outbox;
 pub mod get;
+pub mod impersonate;
+pub mod invite;
+pub mod login_events;
 pub mod picture;
 pub mod root;
 pub mod session;
+pub mod suspend;
@@ -9,9 +9,7 @@ use s3::Bucket;
 use s3::Region;
 use tide::{Response, StatusCode};
 
-pub async fn put_webp_picture_in_bucket(picture: Vec<u8>, picture_name: &str) -> Result<()> {
-    // INSTANTIATE BUCKET
-
+fn get_pictures_bucket() -> Result<Bucket> {
     let bucket = Bucket::new(
         &CONFIG.s3_pictures_bucket,
         Region::Custom {
@@ -29,6 +27,14 @@ pub async fn put_webp_picture_in_bucket(picture: Vec<u8>, picture_name: &str) ->
     .map_err(|err| Error::S3(S3Error::InstantiateBucket(err.to_string())))?
     .with_path_style();
 
+    Ok(bucket)
+}
+
+pub async fn put_webp_picture_in_bucket(picture: Vec<u8>, picture_name: &str) -> Result<()> {
+    // INSTANTIATE BUCKET
+
+    let bucket = get_pictures_bucket()?;
+
     // PUT OBJECT IN BUCKET
 
     let response = bucket
@@ -43,6 +49,48 @@ pub async fn put_webp_picture_in_bucket(picture: Vec<u8>, picture_name: &str) ->
     Ok(())
 }
 
+pub async fn get_picture_from_bucket(picture_name: &str) -> Result<Option<Vec<u8>>> {
+    // INSTANTIATE BUCKET
+
+    let bucket = get_pictures_bucket()?;
+
+    // GET OBJECT FROM BUCKET, ACCOUNTS WITHOUT A PICTURE HAVE NO OBJECT
+
+    let response = match bucket.get_object(picture_name).await {
+        Ok(response) => response,
+        Err(s3::error::S3Error::Http(404, _)) => return Ok(None),
+        Err(err) => return Err(Error::S3(S3Error::GetObject(err.to_string()))),
+    };
+
+    match response.status_code() {
+        200 => Ok(Some(response.bytes().to_vec())),
+        404 => Ok(None),
+        status_code => Err(Error::S3(S3Error::GetObject(f!(
+            "Unexpected status code {}",
+            status_code
+        )))),
+    }
+}
+
+pub async fn delete_picture_from_bucket(picture_name: &str) -> Result<()> {
+    // INSTANTIATE BUCKET
+
+    let bucket = get_pictures_bucket()?;
+
+    // DELETE OBJECT FROM BUCKET, S3 DOESN'T COMPLAIN IF IT NEVER EXISTED
+
+    let response = bucket
+        .delete_object(picture_name)
+        .await
+        .map_err(|err| Error::S3(S3Error::DeleteObject(err.to_string())))?;
+
+    if response.status_code() == 404 {
+        return Err(Error::S3(S3Error::BucketNotFound));
+    }
+
+    Ok(())
+}
+
 pub async fn upload_picture(mut req: tide::Request<()>) -> tide::Result {
     // GET DECODE AND VERIFY TOKEN
 
@@ -55,6 +103,13 @@ pub async fn upload_picture(mut req: tide::Request<()>) -> tide::Result {
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE PICTURE
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET ACCOUNT ID FROM TOKEN
@@ -1,17 +1,32 @@
 use crate::{
+    account_has_permission,
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    client_ip::get_client_ip_address,
     config::CONFIG,
     database::DATABASE_POOL,
-    encryption, get_decode_verify_and_return_session_token, is_account_admin_from_id,
+    email::queue_templated_email,
+    encryption,
+    error::{DatabaseError, Error},
+    geolocation::locate_ip_address,
+    get_decode_verify_and_return_session_token,
     models::{
-        ChangeSessionDeviceDescriptionRequest, ChangeSessionDeviceNameRequest,
-        ChangeSessionDeviceTypeRequest, CreateSessionRequest, DeviceType, Session, SessionList,
-        SessionToken, SessionTokenInfo, Token,
+        AccountSuspended, AuditAction, ChangeSessionDeviceDescriptionRequest,
+        ChangeSessionDeviceNameRequest, ChangeSessionDeviceTypeRequest, CreateSessionRequest,
+        DeviceType, EmailTemplate, LoginEvent, LoginEventOutcome, LoginEventReason, Permission,
+        RevokeSessionRequest, Session, SessionList, SessionRevokeToken, SessionToken,
+        SessionTokenInfo, Token,
     },
+    prelude::*,
     random::get_random_string,
+    routes::login_events::{
+        get_new_login_event_id, is_new_device_for_account, record_login_event,
+    },
     token,
+    user_agent::parse_user_agent,
 };
 use chrono::{Duration, Utc};
 use std::str::FromStr;
+use tera::Context;
 use tide::{convert::json, Response, StatusCode};
 use validator::Validate;
 
@@ -26,6 +41,38 @@ pub async fn create_session(mut req: tide::Request<()>) -> tide::Result {
         return Ok(response);
     };
 
+    // GET USERS IP ADDRESS AND USER AGENT
+
+    let ip_address = get_client_ip_address(&req);
+
+    let user_agent = req.header("User-Agent").map(|value| value.as_str().to_string());
+
+    // GUESS THE DEVICE TYPE AND NAME FROM THE USER AGENT
+
+    let parsed_user_agent = parse_user_agent(user_agent.as_deref().unwrap_or_default());
+
+    let device_name = body
+        .device_name
+        .to_owned()
+        .unwrap_or_else(|| parsed_user_agent.device_name());
+
+    // PREPARE THE LOGIN EVENT TO BE RECORDED
+
+    let mut login_event = LoginEvent {
+        id: get_new_login_event_id(),
+        account_id: None,
+        email: body.email.to_owned(),
+        session_id: None,
+        ip_address: ip_address.to_owned(),
+        user_agent,
+        device_name,
+        device_description: body.device_description.to_owned(),
+        device_type: parsed_user_agent.device_type,
+        outcome: LoginEventOutcome::Failure,
+        reason: LoginEventReason::AccountNotFound,
+        created_at: Utc::now().naive_utc(),
+    };
+
     // BEGIN DATABASE TRANSACTION
 
     let mut transaction = DATABASE_POOL.begin().await?;
@@ -34,22 +81,42 @@ pub async fn create_session(mut req: tide::Request<()>) -> tide::Result {
 
     let query = sqlx::query!(
         r#"
-            SELECT id, password
+            SELECT
+                id,
+                handle,
+                email,
+                password,
+                new_device_sign_in_email,
+                preferred_language,
+                suspension_reason,
+                suspended_until,
+                suspended_at IS NOT NULL
+                    AND (suspended_until IS NULL OR suspended_until > $2)
+                    AS "is_suspended!"
             FROM accounts
             WHERE email = $1
         "#,
-        body.email
+        body.email,
+        login_event.created_at
     );
 
     let result = match query.fetch_optional(&mut *transaction).await? {
         Some(result) => result,
         None => {
+            transaction.rollback().await?;
+            record_login_event(&login_event).await;
             let response = Response::new(StatusCode::NotFound);
             return Ok(response);
         }
     };
 
     let account_id = result.id;
+    let handle = result.handle;
+    let email = result.email;
+    let new_device_sign_in_email = result.new_device_sign_in_email;
+    let preferred_language = result.preferred_language;
+
+    login_event.account_id = Some(account_id.to_owned());
 
     // CHECK IF GIVEN PASSWORD EQUAL TO ENCRYPTED PASSWORD
 
@@ -57,35 +124,52 @@ pub async fn create_session(mut req: tide::Request<()>) -> tide::Result {
         encryption::compare_plain_to_encrypted_string(&body.password, &result.password)?;
 
     if !password_is_correct {
+        transaction.rollback().await?;
+        login_event.reason = LoginEventReason::WrongPassword;
+        record_login_event(&login_event).await;
         let response = Response::new(StatusCode::Unauthorized);
         return Ok(response);
     }
 
-    // GET USERS COUNTRY FROM THE IP ADDRESS
+    // REFUSE TO SIGN IN SUSPENDED ACCOUNTS AND TELL THEM WHY AND UNTIL WHEN
+
+    if result.is_suspended {
+        transaction.rollback().await?;
+        login_event.reason = LoginEventReason::AccountSuspended;
+        record_login_event(&login_event).await;
+        let response = Response::builder(StatusCode::Forbidden)
+            .body(json!(AccountSuspended {
+                reason: result.suspension_reason.unwrap_or_default(),
+                suspended_until: result.suspended_until,
+            }))
+            .build();
+        return Ok(response);
+    }
+
+    // GET USERS COUNTRY AND CITY FROM THE IP ADDRESS
 
-    let ip_address = req
-        .header("X-Forwarded-For")
-        .map(|value| value.as_str())
-        .unwrap_or("127.0.0.1");
+    let geolocation = locate_ip_address(&ip_address);
 
     // INSERT NEW SESSION INTO SESSIONS TABLE
 
     let session_id = get_random_string(CONFIG.session_id_length);
-    let device_type = DeviceType::Other;
     let expire_date = Utc::now().naive_utc() + Duration::days(30);
     let created_at = Utc::now().naive_utc();
 
     let query = sqlx::query!(
         r#"
-            INSERT INTO sessions (id, account_id, device_name, device_description, device_type, ip_address, expire_date, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            INSERT INTO sessions (id, account_id, device_name, device_description, device_type, ip_address, user_agent, country_code, city, expire_date, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         "#,
         session_id,
         account_id,
-        body.device_name,
+        login_event.device_name,
         body.device_description,
-        device_type.to_string(),
+        login_event.device_type.to_string(),
         ip_address,
+        login_event.user_agent,
+        geolocation.country_code,
+        geolocation.city,
         expire_date,
         created_at
     );
@@ -100,14 +184,15 @@ pub async fn create_session(mut req: tide::Request<()>) -> tide::Result {
     // CREATE TOKEN
 
     let session = SessionTokenInfo {
-        id: session_id,
-        account_id,
+        id: session_id.to_owned(),
+        account_id: account_id.to_owned(),
         expire_date,
         created_at,
     };
 
     let session_token = SessionToken {
         session,
+        impersonator_id: None,
         exp: expire_date.and_utc().timestamp() as usize,
     };
 
@@ -117,11 +202,148 @@ pub async fn create_session(mut req: tide::Request<()>) -> tide::Result {
 
     transaction.commit().await?;
 
+    // CHECK IF THE LOGIN COMES FROM A NEW DEVICE
+    // BEFORE RECORDING IT AS A KNOWN ONE, THE SESSION ALREADY
+    // EXISTS SO A FAILURE HERE ONLY SKIPS THE NOTIFICATION
+
+    let is_new_device = is_new_device_for_account(&login_event)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!(
+                "Failed to check the device of login event {}: {}",
+                login_event.id,
+                err
+            );
+            false
+        });
+
+    // RECORD THE SUCCESSFUL LOGIN
+
+    login_event.session_id = Some(session_id.to_owned());
+    login_event.outcome = LoginEventOutcome::Success;
+    login_event.reason = LoginEventReason::SessionCreated;
+
+    record_login_event(&login_event).await;
+
+    // NOTIFY THE OWNER ABOUT THE NEW DEVICE IF THEY DIDN'T OPT OUT
+
+    if is_new_device && new_device_sign_in_email {
+        let revoke_token = token::create_token(&SessionRevokeToken {
+            session_id,
+            account_id,
+            exp: session_token.exp,
+        })?;
+
+        if let Err(err) = send_new_device_sign_in_email(
+            &email,
+            &handle,
+            preferred_language.as_deref(),
+            &login_event,
+            &revoke_token,
+        )
+        .await
+        {
+            log::error!("Failed to queue new device sign in email: {}", err);
+        }
+    }
+
     let response = Response::builder(StatusCode::Ok).body(json!(token)).build();
 
     Ok(response)
 }
 
+async fn send_new_device_sign_in_email(
+    email: &str,
+    handle: &str,
+    preferred_language: Option<&str>,
+    login_event: &LoginEvent,
+    revoke_token: &str,
+) -> Result<()> {
+    // FILL THE EMAIL TEMPLATE WITH THE SIGN IN DETAILS
+
+    let mut context = Context::new();
+    context.insert("handle", handle);
+    context.insert("device_name", &login_event.device_name);
+    context.insert("ip_address", &login_event.ip_address);
+    context.insert(
+        "time",
+        &login_event.created_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    );
+    context.insert(
+        "revoke_link",
+        &f!("{}{}", CONFIG.session_revoke_link, revoke_token),
+    );
+
+    // QUEUE EMAIL
+
+    let mut connection = DATABASE_POOL
+        .acquire()
+        .await
+        .map_err(|err| Error::Database(DatabaseError::Execute(err.to_string())))?;
+
+    queue_templated_email(
+        &mut connection,
+        email,
+        EmailTemplate::NewDeviceSignIn,
+        preferred_language,
+        &context,
+    )
+    .await
+}
+
+pub async fn revoke_session(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: RevokeSessionRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // DECODE THE REVOKE TOKEN SENT BY EMAIL
+
+    let revoke_token: SessionRevokeToken = match token::decode_token(&body.token) {
+        Ok(revoke_token) => revoke_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // DELETE SESSION FROM SESSIONS TABLE WHERE SESSION ID AND ACCOUNT ID MATCH
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM sessions
+            WHERE id = $1 AND account_id = $2
+        "#,
+        revoke_token.session_id,
+        revoke_token.account_id
+    );
+
+    let result = query.execute(&mut *transaction).await?;
+
+    if result.rows_affected() != 1 {
+        let response = Response::new(StatusCode::NotFound);
+        return Ok(response);
+    }
+
+    // COMMIT CHANGES IN DATABASE
+
+    transaction.commit().await?;
+
+    // SEND OK RESPONSE
+
+    Ok(Response::new(StatusCode::Ok))
+}
+
 pub async fn delete_session(req: tide::Request<()>) -> tide::Result {
     // BEGIN DATABASE TRANSACTION
 
@@ -140,22 +362,77 @@ pub async fn delete_session(req: tide::Request<()>) -> tide::Result {
 
     let session = session_token.session;
 
-    // GET ACCOUNT ID FROM OPTIONAL PARAMS IF USER IS ADMIN
+    // AN IMPERSONATION SESSION CAN ONLY END ITSELF, WHICH IS RECORDED IN THE AUDIT LOG
+
+    if let Some(impersonator_id) = session_token.impersonator_id {
+        let is_own_session = match req.param("session_id") {
+            Ok(session_id) => session_id == session.id,
+            _ => true,
+        };
+
+        if !is_own_session {
+            let response = Response::new(StatusCode::Forbidden);
+            return Ok(response);
+        }
+
+        let query = sqlx::query!(
+            r#"
+                DELETE FROM sessions
+                WHERE id = $1 AND account_id = $2
+            "#,
+            session.id,
+            session.account_id
+        );
+
+        let result = query.execute(&mut *transaction).await?;
+
+        if result.rows_affected() != 1 {
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+
+        record_audit_log_entry(
+            &mut transaction,
+            NewAuditLogEntry {
+                actor_id: impersonator_id,
+                target_id: session.account_id,
+                action: AuditAction::ImpersonationEnd,
+                diff: json!({
+                    "before": {
+                        "session_id": session.id,
+                    },
+                    "after": null,
+                }),
+                ip_address: get_client_ip_address(&req),
+            },
+        )
+        .await?;
+
+        // COMMIT CHANGES IN DATABASE
+
+        transaction.commit().await?;
+
+        return Ok(Response::new(StatusCode::Ok));
+    }
+
+    // GET ACCOUNT ID FROM OPTIONAL PARAMS IF USER HAS PERMISSION
 
     let account_id = match req.param("account_id") {
-        Ok(account_id) => match is_account_admin_from_id(&session.id).await {
-            Ok(is_admin) => {
-                if !is_admin {
-                    session.account_id
-                } else {
-                    account_id.to_string()
+        Ok(account_id) => {
+            match account_has_permission(&session.account_id, Permission::SessionsRevoke).await {
+                Ok(has_permission) => {
+                    if !has_permission {
+                        session.account_id
+                    } else {
+                        account_id.to_string()
+                    }
+                }
+                _ => {
+                    let response = Response::new(StatusCode::InternalServerError);
+                    return Ok(response);
                 }
             }
-            _ => {
-                let response = Response::new(StatusCode::InternalServerError);
-                return Ok(response);
-            }
-        },
+        }
         _ => session.account_id,
     };
 
@@ -245,22 +522,24 @@ pub async fn get_some_sessions(req: tide::Request<()>) -> tide::Result {
 
     let session = session_token.session;
 
-    // GET ACCOUNT ID FROM OPTIONAL PARAMS IF USER IS ADMIN
+    // GET ACCOUNT ID FROM OPTIONAL PARAMS IF USER HAS PERMISSION
 
     let account_id = match req.param("account_id") {
-        Ok(account_id) => match is_account_admin_from_id(&session.id).await {
-            Ok(is_admin) => {
-                if !is_admin {
-                    session.account_id
-                } else {
-                    account_id.to_string()
+        Ok(account_id) => {
+            match account_has_permission(&session.account_id, Permission::SessionsRead).await {
+                Ok(has_permission) => {
+                    if !has_permission {
+                        session.account_id
+                    } else {
+                        account_id.to_string()
+                    }
+                }
+                _ => {
+                    let response = Response::new(StatusCode::InternalServerError);
+                    return Ok(response);
                 }
             }
-            _ => {
-                let response = Response::new(StatusCode::InternalServerError);
-                return Ok(response);
-            }
-        },
+        }
         _ => session.account_id,
     };
 
@@ -291,6 +570,10 @@ pub async fn get_some_sessions(req: tide::Request<()>) -> tide::Result {
             device_description: session.device_description,
             device_type: DeviceType::from_str(&session.device_type).unwrap_or(DeviceType::Other),
             ip_address: session.ip_address,
+            user_agent: session.user_agent,
+            country_code: session.country_code,
+            city: session.city,
+            impersonator_id: session.impersonator_id,
             expire_date: session.expire_date,
             created_at: session.created_at,
         })
@@ -339,6 +622,13 @@ pub async fn change_session_device_type(mut req: tide::Request<()>) -> tide::Res
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE DEVICES
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET SESSION AND ACCOUNT IDs FROM TOKEN
@@ -400,6 +690,13 @@ pub async fn change_session_device_name(mut req: tide::Request<()>) -> tide::Res
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE DEVICES
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET SESSION AND ACCOUNT IDs FROM TOKEN
@@ -461,6 +758,13 @@ pub async fn change_session_device_description(mut req: tide::Request<()>) -> ti
         }
     };
 
+    // IMPERSONATION SESSIONS CAN'T CHANGE THE DEVICES
+
+    if session_token.impersonator_id.is_some() {
+        let response = Response::new(StatusCode::Forbidden);
+        return Ok(response);
+    }
+
     let session = session_token.session;
 
     // GET SESSION AND ACCOUNT IDs FROM TOKEN
@@ -0,0 +1,276 @@
+use chrono::Utc;
+use tide::{convert::json, Response, StatusCode};
+use validator::Validate;
+
+use crate::{
+    account_has_permission,
+    audit::{record_audit_log_entry, NewAuditLogEntry},
+    client_ip::get_client_ip_address,
+    database::DATABASE_POOL,
+    get_decode_verify_and_return_session_token,
+    models::{
+        AdminAccountSuspensionRequest, AdminAccountUnsuspensionRequest, AuditAction, Permission,
+    },
+};
+
+pub async fn admin_account_suspension(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: AdminAccountSuspensionRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    let suspended_at = Utc::now().naive_utc();
+
+    // A SUSPENSION THAT WOULD ALREADY BE OVER MAKES NO SENSE
+
+    if body.until.is_some_and(|until| until <= suspended_at) {
+        let response = Response::new(StatusCode::UnprocessableEntity);
+        return Ok(response);
+    }
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO SUSPEND ACCOUNTS
+
+    match account_has_permission(&account_id, Permission::AccountsSuspend).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE CURRENT SUSPENSION FOR THE AUDIT LOG
+
+    let query = sqlx::query!(
+        r#"
+            SELECT suspension_reason, suspended_until
+            FROM accounts
+            WHERE id = $1
+                AND suspended_at IS NOT NULL
+                AND (suspended_until IS NULL OR suspended_until > $2)
+            FOR UPDATE
+        "#,
+        &body.account_id,
+        suspended_at
+    );
+
+    let previous_suspension = query
+        .fetch_optional(&mut *transaction)
+        .await?
+        .map(|result| {
+            json!({
+                "suspension_reason": result.suspension_reason,
+                "suspended_until": result.suspended_until,
+            })
+        });
+
+    // SUSPEND THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE accounts
+            SET
+                suspended_at = $2,
+                suspended_until = $3,
+                suspended_by = $4,
+                suspension_reason = $5
+            WHERE id = $1
+        "#,
+        &body.account_id,
+        suspended_at,
+        body.until,
+        account_id,
+        body.reason
+    );
+
+    let result = query.execute(&mut *transaction).await?;
+
+    if result.rows_affected() != 1 {
+        transaction.rollback().await?;
+        let response = Response::new(StatusCode::NotFound);
+        return Ok(response);
+    }
+
+    // REVOKE ALL LIVE SESSIONS OF THE ACCOUNT
+
+    let query = sqlx::query!(
+        r#"
+            DELETE FROM sessions
+            WHERE account_id = $1
+        "#,
+        &body.account_id
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    // RECORD THE SUSPENSION IN THE AUDIT LOG
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: account_id,
+            target_id: body.account_id.to_owned(),
+            action: AuditAction::AccountSuspension,
+            diff: json!({
+                "before": previous_suspension,
+                "after": {
+                    "suspension_reason": body.reason,
+                    "suspended_until": body.until,
+                },
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE
+
+    let response = Response::new(StatusCode::Ok);
+
+    Ok(response)
+}
+
+pub async fn admin_account_unsuspension(mut req: tide::Request<()>) -> tide::Result {
+    // GET REQUEST BODY AND VALIDATE IT
+
+    let body: AdminAccountUnsuspensionRequest = req.body_json().await?;
+
+    if body.validate().is_err() {
+        let mut response = Response::new(StatusCode::UnprocessableEntity);
+        response.set_error(body.validate().unwrap_err());
+        return Ok(response);
+    };
+
+    // GET DECODE AND VERIFY TOKEN
+
+    let session_token = match get_decode_verify_and_return_session_token(&req).await {
+        Ok(session_token) => session_token,
+        Err(err) => {
+            let mut response = Response::new(StatusCode::Unauthorized);
+            response.set_error(err);
+            return Ok(response);
+        }
+    };
+
+    let account_id = session_token.session.account_id;
+
+    // CHECK IF USER HAS PERMISSION TO SUSPEND ACCOUNTS
+
+    match account_has_permission(&account_id, Permission::AccountsSuspend).await {
+        Ok(has_permission) => {
+            if !has_permission {
+                let response = Response::new(StatusCode::Unauthorized);
+                return Ok(response);
+            }
+        }
+        Err(_) => {
+            let response = Response::new(StatusCode::InternalServerError);
+            return Ok(response);
+        }
+    }
+
+    // BEGIN DATABASE TRANSACTION
+
+    let mut transaction = DATABASE_POOL.begin().await?;
+
+    // GET THE CURRENT SUSPENSION, ONLY ACTIVE ONES CAN BE LIFTED
+
+    let query = sqlx::query!(
+        r#"
+            SELECT suspension_reason, suspended_until
+            FROM accounts
+            WHERE id = $1
+                AND suspended_at IS NOT NULL
+                AND (suspended_until IS NULL OR suspended_until > $2)
+            FOR UPDATE
+        "#,
+        &body.account_id,
+        Utc::now().naive_utc()
+    );
+
+    let previous_suspension = match query.fetch_optional(&mut *transaction).await? {
+        Some(previous_suspension) => previous_suspension,
+        None => {
+            transaction.rollback().await?;
+            let response = Response::new(StatusCode::NotFound);
+            return Ok(response);
+        }
+    };
+
+    // LIFT THE SUSPENSION
+
+    let query = sqlx::query!(
+        r#"
+            UPDATE accounts
+            SET
+                suspended_at = NULL,
+                suspended_until = NULL,
+                suspended_by = NULL,
+                suspension_reason = NULL
+            WHERE id = $1
+        "#,
+        &body.account_id
+    );
+
+    query.execute(&mut *transaction).await?;
+
+    // RECORD THE LIFTED SUSPENSION IN THE AUDIT LOG
+
+    record_audit_log_entry(
+        &mut transaction,
+        NewAuditLogEntry {
+            actor_id: account_id,
+            target_id: body.account_id.to_owned(),
+            action: AuditAction::AccountUnsuspension,
+            diff: json!({
+                "before": {
+                    "suspension_reason": previous_suspension.suspension_reason,
+                    "suspended_until": previous_suspension.suspended_until,
+                },
+                "after": null,
+            }),
+            ip_address: get_client_ip_address(&req),
+        },
+    )
+    .await?;
+
+    // FINALY COMMIT TRANSACTION
+
+    transaction.commit().await?;
+
+    // SEND RESPONSE
+
+    let response = Response::new(StatusCode::Ok);
+
+    Ok(response)
+}
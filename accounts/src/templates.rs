@@ -0,0 +1,88 @@
+use crate::{
+    config::CONFIG,
+    error::{Error, TemplateError},
+    models::EmailTemplate,
+    prelude::*,
+};
+use lazy_static::lazy_static;
+use tera::{Context, Tera};
+
+// Every email is made of three templates in the directory of its locale,
+// "<locale>/<name>.subject", "<locale>/<name>.html" and "<locale>/<name>.txt",
+// the last two usually extending the shared "layout.html" and "layout.txt".
+// The text one can be left out, it's then derived from the rendered HTML
+
+lazy_static! {
+    pub static ref TEMPLATES: Tera =
+        Tera::new(&f!("{}/**/*", CONFIG.email_templates_directory)).unwrap();
+}
+
+// Width the text derived from HTML is wrapped at
+const DERIVED_TEXT_WIDTH: usize = 78;
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: Option<String>,
+    pub text: String,
+}
+
+pub fn render_email(
+    template: EmailTemplate,
+    locale: Option<&str>,
+    context: &Context,
+) -> Result<RenderedEmail> {
+    let locale = resolve_locale(template, locale);
+
+    let render = |extension: &str| {
+        TEMPLATES
+            .render(&f!("{}/{}.{}", locale, template, extension), context)
+            .map_err(|err| Error::Template(TemplateError::Render(f!("{:?}", err))))
+    };
+
+    // AN HTML TEMPLATE THAT RENDERS TO NOTHING MEANS A PLAIN TEXT ONLY EMAIL
+
+    let html = Some(render("html")?).filter(|html| !html.trim().is_empty());
+
+    let text = if has_template(&f!("{}/{}.txt", locale, template)) {
+        render("txt")?
+    } else {
+        let html = html.as_deref().ok_or_else(|| {
+            Error::Template(TemplateError::Render(f!(
+                "{}/{} has neither a text nor an HTML body",
+                locale,
+                template
+            )))
+        })?;
+
+        html2text::from_read(html.as_bytes(), DERIVED_TEXT_WIDTH)
+    };
+
+    Ok(RenderedEmail {
+        subject: render("subject")?.trim().to_string(),
+        html,
+        text,
+    })
+}
+
+fn has_template(name: &str) -> bool {
+    TEMPLATES
+        .get_template_names()
+        .any(|template| template == name)
+}
+
+fn resolve_locale(template: EmailTemplate, locale: Option<&str>) -> String {
+    // TRY THE EXACT LOCALE, THEN ITS LANGUAGE, THEN FALL BACK TO THE DEFAULT ONE
+
+    let candidates = locale
+        .map(|locale| locale.trim().to_lowercase().replace('_', "-"))
+        .map(|locale| {
+            let language = locale.split('-').next().unwrap_or_default().to_string();
+            vec![locale, language]
+        })
+        .unwrap_or_default();
+
+    candidates
+        .into_iter()
+        .find(|candidate| has_template(&f!("{}/{}.subject", candidate, template)))
+        .unwrap_or_else(|| CONFIG.default_locale.to_owned())
+}
@@ -0,0 +1,199 @@
+use crate::{config::CONFIG, models::DeviceType, prelude::*};
+
+pub struct ParsedUserAgent {
+    pub device_type: DeviceType,
+    pub browser: Option<&'static str>,
+    pub operating_system: Option<&'static str>,
+}
+
+impl ParsedUserAgent {
+    pub fn device_name(&self) -> String {
+        let device_name = match (self.browser, self.operating_system) {
+            (Some(browser), Some(operating_system)) => f!("{} on {}", browser, operating_system),
+            (Some(browser), None) => browser.to_string(),
+            (None, Some(operating_system)) => operating_system.to_string(),
+            (None, None) => "Unknown device".to_string(),
+        };
+
+        device_name
+            .chars()
+            .take(CONFIG.device_name_max_length)
+            .collect()
+    }
+}
+
+fn contains_any(user_agent: &str, needles: &[&str]) -> bool {
+    needles.iter().any(|needle| user_agent.contains(needle))
+}
+
+// Short tokens like "cros" also show up inside other words, e.g. "microsoft"
+fn contains_word(user_agent: &str, word: &str) -> bool {
+    user_agent
+        .split(|character: char| !character.is_ascii_alphanumeric())
+        .any(|part| part == word)
+}
+
+fn get_device_type(user_agent: &str) -> DeviceType {
+    // THE ORDER MATTERS, CONSOLES AND TVS ALSO
+    // ANNOUNCE THEMSELVES AS DESKTOP OR MOBILE OPERATING SYSTEMS
+
+    if contains_any(
+        user_agent,
+        &[
+            "smart-tv", "smarttv", "googletv", "appletv", "hbbtv", "netcast", "web0s", "roku",
+            "crkey", "bravia", "aftb", "aftm", "afts", "tizen tv", "android tv",
+        ],
+    ) {
+        return DeviceType::Television;
+    }
+
+    if contains_any(user_agent, &["playstation", "xbox", "nintendo"]) {
+        return DeviceType::Console;
+    }
+
+    if contains_any(user_agent, &["ipad", "tablet", "kindle", "silk/", "playbook"])
+        || (user_agent.contains("android") && !user_agent.contains("mobile"))
+    {
+        return DeviceType::Tablet;
+    }
+
+    if contains_any(
+        user_agent,
+        &[
+            "mobile", "iphone", "ipod", "windows phone", "blackberry", "opera mini",
+        ],
+    ) {
+        return DeviceType::Mobile;
+    }
+
+    if contains_any(user_agent, &["windows nt", "macintosh", "x11", "linux"])
+        || contains_word(user_agent, "cros")
+    {
+        return DeviceType::Desktop;
+    }
+
+    DeviceType::Other
+}
+
+fn get_browser(user_agent: &str) -> Option<&'static str> {
+    let browser = if contains_any(user_agent, &["edg/", "edga/", "edgios/"]) {
+        "Edge"
+    } else if contains_any(user_agent, &["opr/", "opera"]) {
+        "Opera"
+    } else if user_agent.contains("samsungbrowser") {
+        "Samsung Internet"
+    } else if contains_any(user_agent, &["firefox", "fxios"]) {
+        "Firefox"
+    } else if contains_any(user_agent, &["chrome", "crios", "chromium"]) {
+        "Chrome"
+    } else if user_agent.contains("safari") {
+        "Safari"
+    } else if contains_any(user_agent, &["msie", "trident/"]) {
+        "Internet Explorer"
+    } else {
+        return None;
+    };
+
+    Some(browser)
+}
+
+fn get_operating_system(user_agent: &str) -> Option<&'static str> {
+    let operating_system = if user_agent.contains("windows phone") {
+        "Windows Phone"
+    } else if user_agent.contains("xbox") {
+        "Xbox"
+    } else if user_agent.contains("playstation") {
+        "PlayStation"
+    } else if user_agent.contains("nintendo") {
+        "Nintendo"
+    } else if user_agent.contains("windows") {
+        "Windows"
+    } else if contains_any(user_agent, &["iphone", "ipad", "ipod"]) {
+        "iOS"
+    } else if user_agent.contains("android") {
+        "Android"
+    } else if contains_word(user_agent, "cros") {
+        "ChromeOS"
+    } else if contains_any(user_agent, &["mac os x", "macintosh"]) {
+        "macOS"
+    } else if user_agent.contains("linux") {
+        "Linux"
+    } else {
+        return None;
+    };
+
+    Some(operating_system)
+}
+
+pub fn parse_user_agent(user_agent: &str) -> ParsedUserAgent {
+    let user_agent = user_agent.to_lowercase();
+
+    ParsedUserAgent {
+        device_type: get_device_type(&user_agent),
+        browser: get_browser(&user_agent),
+        operating_system: get_operating_system(&user_agent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(user_agent: &str) -> (DeviceType, Option<&'static str>, Option<&'static str>) {
+        let parsed_user_agent = parse_user_agent(user_agent);
+
+        (
+            parsed_user_agent.device_type,
+            parsed_user_agent.browser,
+            parsed_user_agent.operating_system,
+        )
+    }
+
+    #[test]
+    fn desktop_browsers_are_recognized() {
+        assert!(matches!(
+            parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36"),
+            (DeviceType::Desktop, Some("Chrome"), Some("Windows"))
+        ));
+        assert!(matches!(
+            parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.2478.80"),
+            (DeviceType::Desktop, Some("Edge"), Some("Windows"))
+        ));
+        assert!(matches!(
+            parse("Mozilla/5.0 (X11; Linux x86_64; rv:125.0) Gecko/20100101 Firefox/125.0"),
+            (DeviceType::Desktop, Some("Firefox"), Some("Linux"))
+        ));
+    }
+
+    #[test]
+    fn chromeos_is_only_matched_as_a_whole_word() {
+        assert!(matches!(
+            parse("Mozilla/5.0 (X11; CrOS x86_64 14541.0.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36"),
+            (DeviceType::Desktop, Some("Chrome"), Some("ChromeOS"))
+        ));
+        assert!(matches!(
+            parse("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.2478.80"),
+            (DeviceType::Desktop, Some("Edge"), Some("macOS"))
+        ));
+        assert!(matches!(
+            parse("Microsoft Office/16.0 (Macintosh; Mac OS X 10_15_7; Microsoft Outlook 16.84.24041420; Pro)"),
+            (DeviceType::Desktop, None, Some("macOS"))
+        ));
+    }
+
+    #[test]
+    fn mobile_tablet_and_console_devices_are_recognized() {
+        assert!(matches!(
+            parse("Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1"),
+            (DeviceType::Mobile, Some("Safari"), Some("iOS"))
+        ));
+        assert!(matches!(
+            parse("Mozilla/5.0 (Linux; Android 13; SM-X700) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36"),
+            (DeviceType::Tablet, Some("Chrome"), Some("Android"))
+        ));
+        assert!(matches!(
+            parse("Mozilla/5.0 (PlayStation; PlayStation 5/2.26) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/13.0 Safari/605.1.15"),
+            (DeviceType::Console, Some("Safari"), Some("PlayStation"))
+        ));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    config::CONFIG,
+    error::{Error, VerificationError},
+    models::VerificationFlow,
+    prelude::*,
+    random::get_random_code,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+// Every flow that mails a verification code picks its own alphabet, length and
+// time to live, forgot password and password change share the code columns
+// but are checked against the format of the endpoint finishing them
+
+pub struct VerificationCodeFormat {
+    pub alphabet: Vec<char>,
+    pub length: usize,
+    pub ttl: Duration,
+}
+
+lazy_static! {
+    pub static ref VERIFICATION_CODE_FORMATS: HashMap<VerificationFlow, VerificationCodeFormat> =
+        load_verification_code_formats().unwrap();
+}
+
+fn load_verification_code_formats() -> Result<HashMap<VerificationFlow, VerificationCodeFormat>> {
+    let formats = [
+        (
+            VerificationFlow::AccountCreation,
+            &CONFIG.account_creation_code_alphabet,
+            CONFIG.account_creation_code_length,
+            CONFIG.account_creation_code_ttl_seconds,
+        ),
+        (
+            VerificationFlow::EmailChange,
+            &CONFIG.email_change_code_alphabet,
+            CONFIG.email_change_code_length,
+            CONFIG.email_change_code_ttl_seconds,
+        ),
+        (
+            VerificationFlow::PasswordChange,
+            &CONFIG.password_change_code_alphabet,
+            CONFIG.password_change_code_length,
+            CONFIG.password_change_code_ttl_seconds,
+        ),
+        (
+            VerificationFlow::ForgotPassword,
+            &CONFIG.forgot_password_code_alphabet,
+            CONFIG.forgot_password_code_length,
+            CONFIG.forgot_password_code_ttl_seconds,
+        ),
+        (
+            VerificationFlow::AccountDeletion,
+            &CONFIG.account_deletion_code_alphabet,
+            CONFIG.account_deletion_code_length,
+            CONFIG.account_deletion_code_ttl_seconds,
+        ),
+    ];
+
+    formats
+        .into_iter()
+        .map(|(flow, characters, length, ttl_seconds)| {
+            // A REPEATED CHARACTER WOULD COME UP MORE OFTEN THAN THE OTHERS
+
+            let mut alphabet: Vec<char> = Vec::new();
+
+            for character in characters.chars().filter(|c| !c.is_whitespace()) {
+                if !alphabet.contains(&character) {
+                    alphabet.push(character);
+                }
+            }
+
+            if alphabet.is_empty() || length == 0 || ttl_seconds <= 0 {
+                return Err(Error::Verification(VerificationError::InvalidCodeFormat(
+                    flow.to_string(),
+                )));
+            }
+
+            let format = VerificationCodeFormat {
+                alphabet,
+                length,
+                ttl: Duration::seconds(ttl_seconds),
+            };
+
+            Ok((flow, format))
+        })
+        .collect()
+}
+
+pub fn verification_code_format(flow: VerificationFlow) -> &'static VerificationCodeFormat {
+    &VERIFICATION_CODE_FORMATS[&flow]
+}
+
+pub fn generate_verification_code(flow: VerificationFlow) -> String {
+    let format = verification_code_format(flow);
+
+    get_random_code(&format.alphabet, format.length)
+}
+
+pub fn verification_code_expired(flow: VerificationFlow, created_at: NaiveDateTime) -> bool {
+    Utc::now().naive_utc() - created_at > verification_code_format(flow).ttl
+}